@@ -0,0 +1,143 @@
+//! Synchronous wrappers of the async API, for consumers without a runtime of their own.
+//!
+//! Each calling thread lazily builds a current-thread runtime, which can't be nested in another
+//! one: calling these functions from within a tokio runtime fails with
+//! [`Error::BlockingInRuntime`](crate::Error::BlockingInRuntime) instead of blocking it.
+
+use std::future::Future;
+
+use reqwest::Client;
+use tokio::runtime::{Builder, Handle, Runtime};
+use url::Url;
+
+use crate::{
+    io::{
+        download::Manager,
+        file::Hierarchy,
+        sync::{PullReport, RemoteRepository, TrackedIndices},
+    },
+    metadata::{game::VersionInfo, manifest::VersionsManifest},
+};
+
+thread_local! {
+    // Single runtime per thread, so pooled connections of a client outlive a call
+    static RUNTIME: Runtime = Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("failed to build current-thread runtime");
+}
+
+fn block_on<T>(future: impl Future<Output = crate::Result<T>>) -> crate::Result<T> {
+    if Handle::try_current().is_ok() {
+        return Err(crate::Error::BlockingInRuntime);
+    }
+    RUNTIME.with(|runtime| runtime.block_on(future))
+}
+
+pub fn fetch_manifest(client: &Client) -> crate::Result<VersionsManifest> {
+    block_on(crate::resources::fetch_manifest(client))
+}
+
+pub fn fetch_repository(
+    downloader: &Manager,
+    hierarchy: &Hierarchy,
+    remote: Url,
+) -> crate::Result<RemoteRepository> {
+    block_on(RemoteRepository::fetch(downloader, hierarchy, remote))
+}
+
+/// Reads the installed version json, no network access is made.
+pub fn load_version_info(hierarchy: &Hierarchy) -> crate::Result<VersionInfo> {
+    block_on(VersionInfo::load(&VersionInfo::path(hierarchy)))
+}
+
+pub fn track_invalid(repository: &RemoteRepository) -> crate::Result<TrackedIndices<'_>> {
+    block_on(repository.track_invalid())
+}
+
+pub fn pull(
+    tracked: &TrackedIndices<'_>,
+    downloader: &Manager,
+    concurrency: usize,
+) -> crate::Result<PullReport> {
+    block_on(tracked.pull(downloader, concurrency))
+}
+
+/// Fetches the version, pulls every invalid file and hands the repository back, ready to be
+/// turned into a [`GameCommand`](crate::process::GameCommand).
+pub fn sync(
+    downloader: &Manager,
+    hierarchy: &Hierarchy,
+    remote: Url,
+    concurrency: usize,
+) -> crate::Result<RemoteRepository> {
+    block_on(async {
+        let repository = RemoteRepository::fetch(downloader, hierarchy, remote).await?;
+        repository
+            .track_invalid()
+            .await?
+            .pull(downloader, concurrency)
+            .await?;
+        Ok(repository)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{
+        process::GameCommand,
+        testing::{temp_dir, version_json, MockServer},
+    };
+
+    #[test]
+    fn sync_and_build_command() {
+        let server = MockServer::start_in_background();
+        server.set("1.12.2.json", version_json(&server.url("")).to_string());
+        server.set("1.12.json", r#"{"objects": {}}"#);
+        server.set("client.jar", "client");
+        let dir = temp_dir();
+        let hierarchy = Hierarchy::builder(&dir, "1.12.2").build();
+
+        let repository = sync(
+            &Manager::default(),
+            &hierarchy,
+            server.url("1.12.2.json"),
+            4,
+        )
+        .unwrap();
+        let features = HashMap::new();
+        let command = GameCommand::from_version_info(
+            &hierarchy,
+            repository.version_info(),
+            &features,
+            "Steve",
+        );
+
+        assert_eq!(
+            std::fs::read(hierarchy.version_dir.join("client.jar")).unwrap(),
+            b"client"
+        );
+        assert_eq!(
+            command.argv(None),
+            [
+                "net.minecraft.client.main.Main",
+                "--username",
+                "Steve",
+                "--version",
+                "1.12.2"
+            ]
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn calls_from_runtime_are_refused() {
+        assert!(matches!(
+            fetch_manifest(&Client::new()),
+            Err(crate::Error::BlockingInRuntime)
+        ));
+    }
+}
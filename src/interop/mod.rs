@@ -0,0 +1,2 @@
+pub mod modrinth;
+pub mod prism;
@@ -0,0 +1,343 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io,
+    path::{Component, Path, PathBuf},
+};
+
+use serde_derive::Deserialize;
+use tokio::task;
+use tracing::{instrument, trace};
+use url::Url;
+use zip::ZipArchive;
+
+use crate::io::hash::Checksum;
+
+pub static INDEX_FILENAME: &str = "modrinth.index.json";
+pub static MINECRAFT_DEPENDENCY: &str = "minecraft";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Client,
+    Server,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EnvRequirement {
+    Required,
+    Optional,
+    Unsupported,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct FileEnv {
+    pub client: EnvRequirement,
+    pub server: EnvRequirement,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct FileHashes {
+    pub sha1: String,
+    pub sha512: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ModpackFile {
+    pub path: String,
+    pub hashes: FileHashes,
+    pub env: Option<FileEnv>,
+    pub downloads: Vec<Url>,
+    pub file_size: u64,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ModrinthIndex {
+    pub format_version: u32,
+    pub game: String,
+    pub version_id: String,
+    pub name: String,
+    pub summary: Option<String>,
+    pub files: Vec<ModpackFile>,
+    /// Minecraft and loader versions, e.g. `minecraft`, `fabric-loader`, `forge`.
+    pub dependencies: HashMap<String, String>,
+}
+
+#[derive(Debug)]
+pub struct Modpack {
+    archive_path: PathBuf,
+    index: ModrinthIndex,
+}
+
+/// Rejects absolute paths and ones escaping the target directory.
+fn sanitize_path(path: &str) -> Option<&Path> {
+    let path = Path::new(path);
+    path.components()
+        .all(|component| matches!(component, Component::Normal(_)))
+        .then(|| path)
+}
+
+impl ModpackFile {
+    /// Path of the file relative to the game dir, `None` if it's absolute or escapes it.
+    pub fn relative_path(&self) -> Option<&Path> {
+        sanitize_path(&self.path)
+    }
+
+    pub fn checksum(&self) -> Checksum {
+        Checksum::Sha512(self.hashes.sha512.clone())
+    }
+
+    pub fn is_supported_on(&self, side: Side) -> bool {
+        self.env
+            .as_ref()
+            .map(|env| match side {
+                Side::Client => env.client,
+                Side::Server => env.server,
+            })
+            .map(|requirement| requirement != EnvRequirement::Unsupported)
+            .unwrap_or(true)
+    }
+}
+
+impl ModrinthIndex {
+    pub fn minecraft_version(&self) -> Option<&str> {
+        self.dependencies
+            .get(MINECRAFT_DEPENDENCY)
+            .map(String::as_str)
+    }
+
+    pub fn files_for(&self, side: Side) -> impl Iterator<Item = &ModpackFile> {
+        self.files
+            .iter()
+            .filter(move |file| file.is_supported_on(side))
+    }
+}
+
+impl Modpack {
+    #[instrument]
+    pub async fn read(archive_path: PathBuf) -> crate::Result<Self> {
+        let path = archive_path.clone();
+        let index = task::spawn_blocking(move || -> crate::Result<ModrinthIndex> {
+            let mut archive = ZipArchive::new(File::open(path)?)?;
+            let index = archive.by_name(INDEX_FILENAME)?;
+            Ok(serde_json::from_reader(index)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?)
+        })
+        .await??;
+
+        Ok(Self {
+            archive_path,
+            index,
+        })
+    }
+
+    pub fn index(&self) -> &ModrinthIndex {
+        &self.index
+    }
+
+    pub fn bytes_size(&self, side: Side) -> u64 {
+        self.index.files_for(side).map(|file| file.file_size).sum()
+    }
+
+    /// Copies `overrides/` and then the side-specific overrides folder of the archive into
+    /// `game_dir`, so side-specific files win.
+    #[instrument(skip(self))]
+    pub async fn extract_overrides(&self, game_dir: PathBuf, side: Side) -> crate::Result<()> {
+        let archive_path = self.archive_path.clone();
+        task::spawn_blocking(move || -> crate::Result<()> {
+            let side_prefix = match side {
+                Side::Client => "client-overrides/",
+                Side::Server => "server-overrides/",
+            };
+            let mut archive = ZipArchive::new(File::open(archive_path)?)?;
+            for prefix in ["overrides/", side_prefix] {
+                for i in 0..archive.len() {
+                    let mut entry = archive.by_index(i)?;
+                    let name = entry.name().to_owned();
+                    let relative = match name.strip_prefix(prefix).and_then(sanitize_path) {
+                        Some(relative) if !entry.is_dir() => relative,
+                        _ => continue,
+                    };
+                    let output_path = game_dir.join(relative);
+                    trace!(?output_path, "Extracting override");
+                    if let Some(parent) = output_path.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+                    io::copy(&mut entry, &mut File::create(output_path)?)?;
+                }
+            }
+            Ok(())
+        })
+        .await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::{
+        io::{download::Manager, file::Hierarchy, sync::RemoteRepository},
+        testing::{temp_dir, version_json, zip, MockServer},
+    };
+
+    /// Modpack file served by `server`, `content` is expected to be one of the sample ones.
+    fn file_json(server: &MockServer, path: &str, content: &str, env: Value) -> Value {
+        let (sha1, sha512) = match content {
+            "client mod" => (
+                "1114ef9af1fb7ecf2d48988cc294b7ca890839a1",
+                "554b27464814559a9884c61e5ef1b5d7ecad4bff92095634ec54ceed89c66e9b871ea0c794e5470502a6f60db4e3d02e70ff3a86d47d1f88994daca84d212c2d",
+            ),
+            "server mod" => (
+                "ff9848581b8505b6a745cb148527b7ee8f96fd7d",
+                "aafbb7f760ff1cce812f929d948f7f4a407b938f9f3607d30961dbeebbfbdfe67f640d5dadfcba1df4a128428f6b78f7f43ba292c7532b74aed93515a2730b6f",
+            ),
+            "shared lib" => (
+                "a834752574eff9845187112085358904afdc231f",
+                "df5697f7c7b3c676c46b636f38e4bdd644aba18c09e16685bf10c5860e36285d0cd99271257a5a4e90a6b3851dc8f8001b61bd6ff747c337fce87ace6cbd177e",
+            ),
+            _ => unreachable!(),
+        };
+        let name = format!("cdn/{}", path.rsplit('/').next().unwrap());
+        server.set(&name, content);
+        json!({
+            "path": path,
+            "hashes": {"sha1": sha1, "sha512": sha512},
+            "env": env,
+            "downloads": [server.url(&name)],
+            "fileSize": content.len(),
+        })
+    }
+
+    fn sample_files(server: &MockServer) -> Vec<Value> {
+        vec![
+            file_json(
+                server,
+                "mods/client.jar",
+                "client mod",
+                json!({"client": "required", "server": "unsupported"}),
+            ),
+            file_json(
+                server,
+                "mods/server.jar",
+                "server mod",
+                json!({"client": "unsupported", "server": "optional"}),
+            ),
+            file_json(server, "mods/shared.jar", "shared lib", Value::Null),
+        ]
+    }
+
+    async fn write_pack(dir: &Path, files: Vec<Value>) -> Modpack {
+        let index = json!({
+            "formatVersion": 1,
+            "game": "minecraft",
+            "versionId": "1.0.0",
+            "name": "Sample",
+            "files": files,
+            "dependencies": {"minecraft": "1.12.2", "forge": "14.23.5.2859"},
+        })
+        .to_string();
+        let archive_path = dir.join("sample.mrpack");
+        fs::write(&archive_path, zip(&[(INDEX_FILENAME, index.as_bytes())])).unwrap();
+        Modpack::read(archive_path).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn files_are_selected_by_side() {
+        let dir = temp_dir();
+        let server = MockServer::start().await;
+        let modpack = write_pack(&dir, sample_files(&server)).await;
+
+        let index = modpack.index();
+        assert_eq!(index.minecraft_version(), Some("1.12.2"));
+        assert_eq!(index.dependencies["forge"], "14.23.5.2859");
+        let paths = |side| {
+            index
+                .files_for(side)
+                .map(|file| file.path.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(paths(Side::Client), ["mods/client.jar", "mods/shared.jar"]);
+        assert_eq!(paths(Side::Server), ["mods/server.jar", "mods/shared.jar"]);
+        assert_eq!(modpack.bytes_size(Side::Client), 20);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn files_are_pulled_with_the_version() {
+        let dir = temp_dir();
+        let server = MockServer::start().await;
+        server.set("1.12.2.json", version_json(&server.url("")).to_string());
+        server.set("1.12.json", json!({"objects": {}}).to_string());
+        server.set("client.jar", "");
+        let modpack = write_pack(&dir, sample_files(&server)).await;
+        let hierarchy = Hierarchy::builder(dir.join("minecraft"), "1.12.2").build();
+        let downloader = Manager::default();
+        let mut repository =
+            RemoteRepository::fetch(&downloader, &hierarchy, server.url("1.12.2.json"))
+                .await
+                .unwrap();
+
+        repository
+            .add_modpack(&modpack, &hierarchy.gamedir, Side::Client)
+            .unwrap();
+        repository
+            .track_invalid()
+            .await
+            .unwrap()
+            .pull(&downloader, 4)
+            .await
+            .unwrap();
+
+        let mods_dir = hierarchy.gamedir.join("mods");
+        assert_eq!(
+            fs::read(mods_dir.join("client.jar")).unwrap(),
+            b"client mod"
+        );
+        assert_eq!(
+            fs::read(mods_dir.join("shared.jar")).unwrap(),
+            b"shared lib"
+        );
+        assert!(!mods_dir.join("server.jar").exists());
+        // valid files aren't downloaded again
+        repository
+            .track_invalid()
+            .await
+            .unwrap()
+            .pull(&downloader, 4)
+            .await
+            .unwrap();
+        assert_eq!(server.hits("cdn/client.jar"), 1);
+        assert_eq!(server.hits("cdn/shared.jar"), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn escaping_path_is_rejected() {
+        let dir = temp_dir();
+        let server = MockServer::start().await;
+        server.set("1.12.2.json", version_json(&server.url("")).to_string());
+        server.set("1.12.json", json!({"objects": {}}).to_string());
+        let files = vec![file_json(
+            &server,
+            "../outside.jar",
+            "shared lib",
+            Value::Null,
+        )];
+        let modpack = write_pack(&dir, files).await;
+        let hierarchy = Hierarchy::builder(dir.join("minecraft"), "1.12.2").build();
+        let mut repository =
+            RemoteRepository::fetch(&Manager::default(), &hierarchy, server.url("1.12.2.json"))
+                .await
+                .unwrap();
+
+        assert!(repository
+            .add_modpack(&modpack, &hierarchy.gamedir, Side::Client)
+            .is_err());
+        assert_eq!(repository.bytes_size(), 0);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
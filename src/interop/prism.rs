@@ -0,0 +1,115 @@
+use std::{io, path::Path};
+
+use serde_json::json;
+use tokio::fs;
+use tracing::{instrument, trace};
+
+use crate::{io::file::Hierarchy, metadata::game::VersionInfo};
+
+pub static INSTANCE_CFG_FILENAME: &str = "instance.cfg";
+pub static MMC_PACK_FILENAME: &str = "mmc-pack.json";
+
+/// Writes PrismLauncher/MultiMC instance files describing a vanilla install into `instance_dir`.
+/// Prism expects the game directory inside of it, named `.minecraft` or `minecraft`, so other
+/// hierarchies are rejected, as is a multiline `name`.
+#[instrument(skip(info))]
+pub async fn export_instance(
+    hierarchy: &Hierarchy,
+    info: &VersionInfo,
+    name: &str,
+    instance_dir: &Path,
+) -> crate::Result<()> {
+    if name.contains(['\n', '\r']) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "instance name must be a single line",
+        )
+        .into());
+    }
+    if ![".minecraft", "minecraft"]
+        .iter()
+        .any(|gamedir| hierarchy.gamedir == instance_dir.join(gamedir))
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "gamedir {} isn't `minecraft` or `.minecraft` of the instance dir",
+                hierarchy.gamedir.display()
+            ),
+        )
+        .into());
+    }
+    fs::create_dir_all(instance_dir).await?;
+
+    let instance_cfg = format!(
+        "[General]\nConfigVersion=1.2\nInstanceType=OneSix\nname={}\n",
+        name
+    );
+    fs::write(instance_dir.join(INSTANCE_CFG_FILENAME), instance_cfg).await?;
+
+    let mmc_pack = json!({
+        "components": [
+            {
+                "cachedName": "Minecraft",
+                "cachedVersion": info.id,
+                "important": true,
+                "uid": "net.minecraft",
+                "version": info.id,
+            }
+        ],
+        "formatVersion": 1,
+    });
+    let filebuf = serde_json::to_vec_pretty(&mmc_pack)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    fs::write(instance_dir.join(MMC_PACK_FILENAME), filebuf).await?;
+    trace!(?instance_dir, "Exported instance");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+    use url::Url;
+
+    use super::*;
+    use crate::testing::{temp_dir, version_json};
+
+    #[tokio::test]
+    async fn pack_references_game_version() {
+        let dir = temp_dir();
+        let info: VersionInfo = serde_json::from_value(version_json(
+            &Url::parse("https://example.invalid/").unwrap(),
+        ))
+        .unwrap();
+        let hierarchy = Hierarchy::builder(dir.join(".minecraft"), &info.id).build();
+
+        export_instance(&hierarchy, &info, "My instance", &dir)
+            .await
+            .unwrap();
+
+        let pack: Value =
+            serde_json::from_slice(&std::fs::read(dir.join(MMC_PACK_FILENAME)).unwrap()).unwrap();
+        assert_eq!(pack["components"][0]["uid"], "net.minecraft");
+        assert_eq!(pack["components"][0]["version"], "1.12.2");
+        let cfg = std::fs::read_to_string(dir.join(INSTANCE_CFG_FILENAME)).unwrap();
+        assert!(cfg.lines().any(|line| line == "name=My instance"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn foreign_gamedir_is_rejected() {
+        let dir = temp_dir();
+        let info: VersionInfo = serde_json::from_value(version_json(
+            &Url::parse("https://example.invalid/").unwrap(),
+        ))
+        .unwrap();
+        let hierarchy = Hierarchy::builder(dir.join("games"), &info.id).build();
+
+        assert!(export_instance(&hierarchy, &info, "My instance", &dir)
+            .await
+            .is_err());
+        assert!(!dir.join(MMC_PACK_FILENAME).exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
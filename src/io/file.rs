@@ -1,4 +1,97 @@
-use std::path::PathBuf;
+use std::{
+    env, io,
+    path::{Component, Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::metadata::assets::AssetMetadata;
+
+/// Creates an entry in the temp dir with `create`, which must fail with `AlreadyExists` on an
+/// existing path (e.g. `create_new` or `create_dir`). Names are hard to guess and taken ones are
+/// skipped, so nothing planted in a shared temp dir, such as a symlink, is followed.
+pub(crate) fn create_temp<T>(
+    suffix: &str,
+    create: impl FnMut(&Path) -> io::Result<T>,
+) -> io::Result<(PathBuf, T)> {
+    create_temp_in(&env::temp_dir(), suffix, create)
+}
+
+/// Same as [`create_temp`], in `dir`, e.g. to rename the entry into `dir` once it's complete.
+pub(crate) fn create_temp_in<T>(
+    dir: &Path,
+    suffix: &str,
+    mut create: impl FnMut(&Path) -> io::Result<T>,
+) -> io::Result<(PathBuf, T)> {
+    const ATTEMPTS: usize = 16;
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    for _ in 0..ATTEMPTS {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.subsec_nanos())
+            .unwrap_or_default();
+        let path = dir.join(format!(
+            "{}-{}-{}-{:08x}{}",
+            env!("CARGO_PKG_NAME"),
+            process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed),
+            nanos,
+            suffix
+        ));
+        match create(&path) {
+            Ok(created) => return Ok((path, created)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        "no free name in the temp dir",
+    ))
+}
+
+/// Whether `name`, e.g. of an archive entry or a legacy asset, stays inside the dir it's joined to.
+pub(crate) fn is_enclosed(name: &str) -> bool {
+    !name.contains('\0')
+        && Path::new(name)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+/// `dir` joined with `name`, failing if the name escapes it.
+pub(crate) fn join_enclosed(dir: &Path, name: &str) -> crate::Result<PathBuf> {
+    if !is_enclosed(name) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} escapes {}", name, dir.display()),
+        )
+        .into());
+    }
+    Ok(dir.join(name))
+}
+
+/// Where an asset is stored in `assets_dir`: `objects/<2 first chars of hash>/<hash>`, or
+/// `virtual/legacy/<name>` for legacy (`virtual`) indices.
+pub fn asset_object_path(
+    assets_dir: &Path,
+    name: &str,
+    metadata: &AssetMetadata,
+    legacy: bool,
+) -> crate::Result<PathBuf> {
+    if legacy {
+        return join_enclosed(&assets_dir.join("virtual/legacy"), name);
+    }
+    let hash = &metadata.hash;
+    let prefix = hash
+        .get(..2)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "asset hash is too short"))?;
+    Ok(assets_dir.join("objects").join(prefix).join(hash))
+}
+
+/// Id of the asset index used by versions before 1.6.
+pub const PRE_1_6_ASSETS: &str = "pre-1.6";
 
 #[derive(Debug)]
 pub struct Hierarchy {
@@ -9,18 +102,112 @@ pub struct Hierarchy {
     pub natives_dir: PathBuf,
 }
 
+#[derive(Debug)]
+pub struct HierarchyBuilder {
+    root: PathBuf,
+    id: String,
+    gamedir: Option<PathBuf>,
+    assets_dir: Option<PathBuf>,
+    libraries_dir: Option<PathBuf>,
+    version_dir: Option<PathBuf>,
+    natives_dir: Option<PathBuf>,
+}
+
 impl Hierarchy {
-    pub fn with_default_structure(id: &str) -> Self {
-        let gamedir = dirs::data_dir()
+    pub fn builder(root: impl Into<PathBuf>, id: &str) -> HierarchyBuilder {
+        HierarchyBuilder::new(root, id)
+    }
+
+    pub fn try_with_default_structure(id: &str) -> crate::Result<Self> {
+        Self::from_base_dirs(id, dirs::data_dir(), dirs::home_dir())
+    }
+
+    /// `.minecraft` layout rooted in the data dir, or in home if there's no data dir.
+    fn from_base_dirs(
+        id: &str,
+        data_dir: Option<PathBuf>,
+        home_dir: Option<PathBuf>,
+    ) -> crate::Result<Self> {
+        let gamedir = data_dir
             .map(|data| data.join("minecraft"))
-            .or_else(|| dirs::home_dir().map(|home| home.join(".minecraft")))
-            .expect("neither home nor data dirs found");
-        let assets_dir = gamedir.join("assets/");
-        let libraries_dir = gamedir.join("libraries/");
-        let version_dir = gamedir.join(format!("versions/{}", id));
-        let natives_dir = version_dir.join("natives/");
+            .or_else(|| home_dir.map(|home| home.join(".minecraft")))
+            .ok_or(crate::Error::NoDefaultDirectory)?;
+        Ok(HierarchyBuilder::new(gamedir, id).build())
+    }
+
+    pub fn with_default_structure(id: &str) -> Self {
+        Self::try_with_default_structure(id).expect("neither home nor data dirs found")
+    }
 
+    /// Where assets of the pre-1.6 index are stored.
+    pub fn resources_dir(&self) -> PathBuf {
+        self.gamedir.join("resources")
+    }
+
+    /// Value of `${game_assets}` used by legacy versions for the asset index `assets_id`.
+    pub fn game_assets_dir(&self, assets_id: &str) -> PathBuf {
+        match assets_id {
+            PRE_1_6_ASSETS => self.resources_dir(),
+            _ => self.assets_dir.join("virtual/legacy"),
+        }
+    }
+}
+
+impl HierarchyBuilder {
+    /// Every directory not overridden explicitly is derived from `root` using the standard
+    /// `.minecraft` layout.
+    pub fn new(root: impl Into<PathBuf>, id: &str) -> Self {
         Self {
+            root: root.into(),
+            id: id.to_owned(),
+            gamedir: None,
+            assets_dir: None,
+            libraries_dir: None,
+            version_dir: None,
+            natives_dir: None,
+        }
+    }
+
+    pub fn gamedir(mut self, path: impl Into<PathBuf>) -> Self {
+        self.gamedir = Some(path.into());
+        self
+    }
+
+    pub fn assets_dir(mut self, path: impl Into<PathBuf>) -> Self {
+        self.assets_dir = Some(path.into());
+        self
+    }
+
+    pub fn libraries_dir(mut self, path: impl Into<PathBuf>) -> Self {
+        self.libraries_dir = Some(path.into());
+        self
+    }
+
+    pub fn version_dir(mut self, path: impl Into<PathBuf>) -> Self {
+        self.version_dir = Some(path.into());
+        self
+    }
+
+    pub fn natives_dir(mut self, path: impl Into<PathBuf>) -> Self {
+        self.natives_dir = Some(path.into());
+        self
+    }
+
+    pub fn build(self) -> Hierarchy {
+        let root = self.root;
+        let assets_dir = self.assets_dir.unwrap_or_else(|| root.join("assets/"));
+        let libraries_dir = self
+            .libraries_dir
+            .unwrap_or_else(|| root.join("libraries/"));
+        let version_dir = self
+            .version_dir
+            .unwrap_or_else(|| root.join(format!("versions/{}", self.id)));
+        let natives_dir = self
+            .natives_dir
+            .unwrap_or_else(|| version_dir.join("natives/"));
+        let gamedir = self.gamedir.unwrap_or(root);
+
+        Hierarchy {
             gamedir,
             assets_dir,
             libraries_dir,
@@ -29,3 +216,113 @@ impl Hierarchy {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    #[test]
+    fn versions_have_distinct_natives_dirs() {
+        let first = Hierarchy::with_default_structure("1.12.2");
+        let second = Hierarchy::with_default_structure("1.16.5");
+        assert_ne!(first.natives_dir, second.natives_dir);
+        assert!(first.natives_dir.starts_with(&first.version_dir));
+        assert!(second.natives_dir.starts_with(&second.version_dir));
+    }
+
+    #[test]
+    fn builder_defaults_follow_standard_layout() {
+        let hierarchy = Hierarchy::builder("/games/mc", "1.16.5").build();
+        assert_eq!(hierarchy.gamedir, Path::new("/games/mc"));
+        assert_eq!(hierarchy.assets_dir, Path::new("/games/mc/assets"));
+        assert_eq!(hierarchy.libraries_dir, Path::new("/games/mc/libraries"));
+        assert_eq!(
+            hierarchy.version_dir,
+            Path::new("/games/mc/versions/1.16.5")
+        );
+        assert_eq!(
+            hierarchy.natives_dir,
+            Path::new("/games/mc/versions/1.16.5/natives")
+        );
+    }
+
+    #[test]
+    fn builder_overrides_every_directory() {
+        let hierarchy = Hierarchy::builder("/games/mc", "1.16.5")
+            .gamedir("/profiles/survival")
+            .assets_dir("/shared/assets")
+            .libraries_dir("/shared/libraries")
+            .version_dir("/shared/versions/1.16.5")
+            .natives_dir("/tmp/natives")
+            .build();
+        assert_eq!(hierarchy.gamedir, Path::new("/profiles/survival"));
+        assert_eq!(hierarchy.assets_dir, Path::new("/shared/assets"));
+        assert_eq!(hierarchy.libraries_dir, Path::new("/shared/libraries"));
+        assert_eq!(hierarchy.version_dir, Path::new("/shared/versions/1.16.5"));
+        assert_eq!(hierarchy.natives_dir, Path::new("/tmp/natives"));
+    }
+
+    #[test]
+    fn default_structure_falls_back_to_home() {
+        let hierarchy =
+            Hierarchy::from_base_dirs("1.16.5", None, Some(PathBuf::from("/home/steve"))).unwrap();
+        assert_eq!(hierarchy.gamedir, Path::new("/home/steve/.minecraft"));
+
+        let hierarchy = Hierarchy::from_base_dirs(
+            "1.16.5",
+            Some(PathBuf::from("/data")),
+            Some(PathBuf::from("/home/steve")),
+        )
+        .unwrap();
+        assert_eq!(hierarchy.gamedir, Path::new("/data/minecraft"));
+    }
+
+    #[test]
+    fn missing_base_dirs_is_an_error() {
+        assert!(matches!(
+            Hierarchy::from_base_dirs("1.16.5", None, None),
+            Err(crate::Error::NoDefaultDirectory)
+        ));
+    }
+
+    fn metadata(hash: &str) -> AssetMetadata {
+        AssetMetadata {
+            hash: hash.to_owned(),
+            size: 0,
+        }
+    }
+
+    #[test]
+    fn object_path_is_split_by_hash_prefix() {
+        let path = asset_object_path(Path::new("assets"), "a.ogg", &metadata("abcd"), false);
+        assert_eq!(path.unwrap(), Path::new("assets/objects/ab/abcd"));
+    }
+
+    #[test]
+    fn legacy_path_uses_name() {
+        let path = asset_object_path(Path::new("assets"), "sound/a.ogg", &metadata("a"), true);
+        assert_eq!(
+            path.unwrap(),
+            Path::new("assets/virtual/legacy/sound/a.ogg")
+        );
+    }
+
+    #[test]
+    fn short_hash_is_rejected() {
+        for hash in ["", "a"] {
+            assert!(
+                asset_object_path(Path::new("assets"), "a.ogg", &metadata(hash), false).is_err()
+            );
+        }
+    }
+
+    #[test]
+    fn traversing_legacy_name_is_rejected() {
+        for name in ["../../escape.ogg", "/etc/passwd", "sound/../../a.ogg"] {
+            let path = asset_object_path(Path::new("assets"), name, &metadata("abcd"), true);
+            assert!(path.is_err(), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,151 @@
+#[cfg(feature = "sha1")]
+use std::{fs::File, io::Read, path::PathBuf};
+
+use serde_derive::{Deserialize, Serialize};
+#[cfg(feature = "sha1")]
+use sha1::{Digest, Sha1};
+#[cfg(feature = "sha1")]
+use sha2::Sha512;
+#[cfg(feature = "sha1")]
+use tracing::instrument;
+
+#[cfg(feature = "sha1")]
+use super::pool::BlockingPool;
+
+#[cfg(feature = "sha1")]
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Expected hex-encoded digest of a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Checksum {
+    Sha1(String),
+    Sha512(String),
+    None,
+}
+
+/// Checks a file goes through before it's considered valid, weaker levels order first.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ValidationLevel {
+    /// Existence and size only, a corrupted file of the right length passes.
+    Size,
+    /// Size, then the checksum.
+    Checksum,
+}
+
+impl ValidationLevel {
+    /// Level validation runs at, checksums are verified with the `sha1` feature only.
+    pub const fn current() -> Self {
+        if cfg!(feature = "sha1") {
+            Self::Checksum
+        } else {
+            Self::Size
+        }
+    }
+}
+
+impl Checksum {
+    /// Whether the file at `path` matches the checksum, always true for [`Checksum::None`].
+    #[cfg(feature = "sha1")]
+    pub async fn verify(&self, path: PathBuf) -> crate::Result<bool> {
+        self.verify_in(path, &BlockingPool::default()).await
+    }
+
+    /// Same as [`Self::verify`], but the file is hashed in `pool`.
+    #[cfg(feature = "sha1")]
+    pub async fn verify_in(&self, path: PathBuf, pool: &BlockingPool) -> crate::Result<bool> {
+        let (expected, actual) = match self {
+            Self::Sha1(expected) => (expected, sha1_digest_in(path, pool).await?),
+            Self::Sha512(expected) => (expected, sha512_digest_in(path, pool).await?),
+            Self::None => return Ok(true),
+        };
+        Ok(actual.eq_ignore_ascii_case(expected))
+    }
+}
+
+/// Hex-encoded digest of the file. It's read in chunks on a thread of `pool`, so memory usage
+/// doesn't depend on the file size.
+#[cfg(feature = "sha1")]
+async fn digest<D: Digest + Send + 'static>(
+    path: PathBuf,
+    pool: &BlockingPool,
+) -> crate::Result<String> {
+    pool.run(move || {
+        let mut file = File::open(path)?;
+        let mut hasher = D::new();
+        let mut buf = vec![0; CHUNK_SIZE];
+        loop {
+            let len = file.read(&mut buf)?;
+            if len == 0 {
+                break;
+            }
+            hasher.update(&buf[..len]);
+        }
+        Ok(hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect())
+    })
+    .await?
+}
+
+#[cfg(feature = "sha1")]
+pub async fn sha1_digest(path: PathBuf) -> crate::Result<String> {
+    sha1_digest_in(path, &BlockingPool::default()).await
+}
+
+/// Same as [`sha1_digest`], but the file is hashed in `pool`.
+#[cfg(feature = "sha1")]
+#[instrument(name = "sha1_digest", skip(pool))]
+pub async fn sha1_digest_in(path: PathBuf, pool: &BlockingPool) -> crate::Result<String> {
+    digest::<Sha1>(path, pool).await
+}
+
+#[cfg(feature = "sha1")]
+pub async fn sha512_digest(path: PathBuf) -> crate::Result<String> {
+    sha512_digest_in(path, &BlockingPool::default()).await
+}
+
+/// Same as [`sha512_digest`], but the file is hashed in `pool`.
+#[cfg(feature = "sha1")]
+#[instrument(name = "sha512_digest", skip(pool))]
+pub async fn sha512_digest_in(path: PathBuf, pool: &BlockingPool) -> crate::Result<String> {
+    digest::<Sha512>(path, pool).await
+}
+
+#[cfg(all(test, feature = "sha1"))]
+mod tests {
+    use super::*;
+    use crate::testing::temp_dir;
+
+    #[tokio::test]
+    async fn chunked_digest_equals_one_shot_digest() {
+        let dir = temp_dir();
+        let path = dir.join("large.bin");
+        let content: Vec<u8> = (0..3 * CHUNK_SIZE + 123).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&path, &content).unwrap();
+
+        let streamed = sha1_digest(path).await.unwrap();
+        assert_eq!(streamed, format!("{:x}", Sha1::digest(&content)));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn sha512_checksum_is_verified() {
+        let dir = temp_dir();
+        let path = dir.join("mod.jar");
+        std::fs::write(&path, b"shared lib").unwrap();
+        let expected = format!("{:x}", Sha512::digest(b"shared lib"));
+
+        assert!(Checksum::Sha512(expected.to_uppercase())
+            .verify(path.clone())
+            .await
+            .unwrap());
+        assert!(!Checksum::Sha512(format!("{:x}", Sha512::digest(b"other")))
+            .verify(path.clone())
+            .await
+            .unwrap());
+        assert!(Checksum::None.verify(path).await.unwrap());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,3 +1,5 @@
 pub mod download;
 pub mod file;
+pub mod hash;
+pub mod pool;
 pub mod sync;
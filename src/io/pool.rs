@@ -0,0 +1,54 @@
+use std::{
+    fmt::{self, Debug},
+    io,
+    sync::Arc,
+};
+
+use tokio::{sync::oneshot, task};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Where CPU-bound work (hashing and natives extraction) runs, tokio's blocking pool by default.
+/// A dedicated pool keeps heavy validation from starving other blocking work of the runtime.
+#[derive(Clone, Default)]
+pub struct BlockingPool {
+    spawn: Option<Arc<dyn Fn(Job) + Send + Sync>>,
+}
+
+impl BlockingPool {
+    /// Jobs are handed to `spawn`, e.g. `move |job| rayon_pool.spawn(job)`.
+    pub fn new(spawn: impl Fn(Box<dyn FnOnce() + Send>) + Send + Sync + 'static) -> Self {
+        Self {
+            spawn: Some(Arc::new(spawn)),
+        }
+    }
+
+    pub async fn run<T, F>(&self, f: F) -> crate::Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        match &self.spawn {
+            Some(spawn) => {
+                let (tx, rx) = oneshot::channel();
+                spawn(Box::new(move || {
+                    let _ = tx.send(f());
+                }));
+                rx.await.map_err(|_| {
+                    io::Error::new(io::ErrorKind::Other, "blocking job dropped by the pool").into()
+                })
+            }
+            None => Ok(task::spawn_blocking(f).await?),
+        }
+    }
+}
+
+impl Debug for BlockingPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(if self.spawn.is_some() {
+            "BlockingPool::Custom"
+        } else {
+            "BlockingPool::Tokio"
+        })
+    }
+}
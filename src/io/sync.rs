@@ -1,30 +1,51 @@
 use std::{
+    collections::{HashMap, HashSet},
     fmt::Debug,
+    future::Future,
     io::{self, Cursor},
-    path::PathBuf,
+    path::{Path, PathBuf},
+    time::SystemTime,
 };
 
-use futures_util::{stream, StreamExt, TryStreamExt};
-use tokio::{fs, task};
-use tracing::{instrument, trace};
+use futures_util::{
+    future::{self, Either},
+    pin_mut, stream, StreamExt, TryStreamExt,
+};
+use serde_derive::{Deserialize, Serialize};
+use tokio::fs;
+use tracing::{field, instrument, trace, trace_span, warn};
 use url::Url;
 use zip::ZipArchive;
 
 use crate::{
-    io::download::Manager,
+    interop::modrinth::{Modpack, Side},
+    io::{
+        download::{partial_path, Manager},
+        hash::{Checksum, ValidationLevel},
+        pool::BlockingPool,
+    },
     metadata::{
         assets::{AssetIndex, AssetMetadata},
-        game::{Resource, VersionInfo},
+        game::{Resource, Target, VersionInfo},
+        manifest::Version,
     },
-    resources::get_asset_url,
+    resources::{get_asset_url, Hosts},
 };
 
-use super::file::Hierarchy;
+use super::file::{asset_object_path, create_temp_in, is_enclosed, join_enclosed, Hierarchy};
+
+fn warn_weak_validation() {
+    if ValidationLevel::current() == ValidationLevel::Size {
+        warn!("Checksums aren't verified without the `sha1` feature, files are checked by size");
+    }
+}
 
 #[derive(Debug)]
 struct RemoteMetadata {
     url: Url,
     size: u64,
+    #[cfg_attr(not(feature = "sha1"), allow(dead_code))]
+    checksum: Checksum,
 }
 
 impl From<&Resource> for RemoteMetadata {
@@ -32,6 +53,7 @@ impl From<&Resource> for RemoteMetadata {
         Self {
             url: res.url.clone(),
             size: res.size,
+            checksum: res.checksum(),
         }
     }
 }
@@ -39,26 +61,67 @@ impl From<&Resource> for RemoteMetadata {
 #[derive(Debug)]
 enum IndexType {
     GameFile,
-    NativeArtifact { extract_dir: PathBuf },
+    NativeArtifact {
+        extract_dir: PathBuf,
+        /// Shared dir the artifact is extracted to once, and linked into `extract_dir` from.
+        cache_dir: Option<PathBuf>,
+    },
+}
+
+/// Read-only view of a file managed by [`RemoteRepository`].
+#[derive(Debug, Clone, Copy)]
+pub struct IndexInfo<'a> {
+    pub category: IndexCategory,
+    pub url: &'a Url,
+    pub local_path: &'a Path,
+    pub size: u64,
+    /// Expected sha1 of the file, if known.
+    pub sha1: Option<&'a str>,
+    /// Where the file is extracted to after download, for native artifacts only.
+    pub extract_dir: Option<&'a Path>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IndexCategory {
+    /// Version json and asset index.
+    Metadata,
+    Asset,
+    Library,
+    Native,
+    Client,
+    Logging,
+    Server,
+    Mappings,
+    /// Files added by [`RemoteRepository::add_modpack`].
+    Modpack,
 }
 
 #[derive(Debug)]
 struct Index {
+    category: IndexCategory,
     metadata: RemoteMetadata,
     local_path: PathBuf,
     itype: IndexType,
 }
 
+impl IndexCategory {
+    /// Whether the game can't be launched without files of the category.
+    pub fn is_critical(self) -> bool {
+        self != Self::Asset
+    }
+}
+
 impl Index {
     #[instrument]
-    async fn validate(&self) -> crate::Result<bool> {
+    async fn validate(&self, pool: &BlockingPool) -> crate::Result<bool> {
         if !self.local_path.exists() {
             trace!("Path not exists");
             return Ok(false);
         }
 
         let metadata = fs::metadata(&self.local_path).await?;
-        if metadata.len() != self.metadata.size {
+        // zero means the size isn't known upfront
+        if self.metadata.size != 0 && metadata.len() != self.metadata.size {
             trace!(
                 actual_len = metadata.len(),
                 expected_len = self.metadata.size,
@@ -67,179 +130,2806 @@ impl Index {
             return Ok(false);
         }
 
+        #[cfg(feature = "sha1")]
+        {
+            if !self
+                .metadata
+                .checksum
+                .verify_in(self.local_path.clone(), pool)
+                .await?
+            {
+                trace!(expected = ?self.metadata.checksum, "Mismatch checksum");
+                return Ok(false);
+            }
+        }
+
         Ok(true)
     }
+
+    fn info(&self) -> IndexInfo<'_> {
+        IndexInfo {
+            category: self.category,
+            url: &self.metadata.url,
+            local_path: &self.local_path,
+            size: self.metadata.size,
+            sha1: match &self.metadata.checksum {
+                Checksum::Sha1(hash) => Some(hash),
+                Checksum::Sha512(_) | Checksum::None => None,
+            },
+            extract_dir: self.extract_dir(),
+        }
+    }
+
+    /// What's recorded in [`ValidationState`] once the file is validated, `None` for files
+    /// without a checksum as they're cheap to validate anyway.
+    fn validated_file(&self, metadata: &std::fs::Metadata) -> Option<ValidatedFile> {
+        let checksum = match &self.metadata.checksum {
+            Checksum::Sha1(hash) | Checksum::Sha512(hash) => hash.clone(),
+            Checksum::None => return None,
+        };
+        Some(ValidatedFile {
+            checksum,
+            size: metadata.len(),
+            modified: metadata.modified().ok()?,
+            level: ValidationLevel::current(),
+        })
+    }
+
+    fn extract_dir(&self) -> Option<&Path> {
+        match &self.itype {
+            IndexType::NativeArtifact { extract_dir, .. } => Some(extract_dir),
+            IndexType::GameFile => None,
+        }
+    }
+
     #[instrument]
-    async fn pull(&self, downloader: &Manager) -> crate::Result<()> {
+    async fn pull(&self, downloader: &Manager, pool: &BlockingPool) -> crate::Result<()> {
+        // produced locally, e.g. by an installer, so there's nowhere to download it from
+        if self.metadata.url.scheme() == "file" {
+            return Err(crate::Error::MissingLocalArtifact(self.local_path.clone()));
+        }
         downloader
-            .download_file(self.metadata.url.clone(), &self.local_path)
+            .download_categorized(self.metadata.url.clone(), &self.local_path, self.category)
             .await?;
-        if let IndexType::NativeArtifact { extract_dir } = &self.itype {
-            let filebuf = fs::read(&self.local_path).await?;
-            let extract_dir = extract_dir.clone();
-            // TODO : span here
-            task::spawn_blocking(move || {
-                let mut cursor = Cursor::new(filebuf);
-                let mut native_artifact = ZipArchive::new(&mut cursor)?;
-                native_artifact.extract(extract_dir)
-            })
-            .await??;
+        self.extract(pool).await
+    }
+
+    #[instrument]
+    async fn extract(&self, pool: &BlockingPool) -> crate::Result<()> {
+        let (extract_dir, cache_dir) = match &self.itype {
+            IndexType::NativeArtifact {
+                extract_dir,
+                cache_dir,
+            } => (extract_dir.clone(), cache_dir.clone()),
+            IndexType::GameFile => return Ok(()),
+        };
+        // the cache is keyed by the artifact's checksum, natives of unknown one are extracted as is
+        let cached = match (cache_dir, &self.metadata.checksum) {
+            (Some(cache_dir), Checksum::Sha1(hash) | Checksum::Sha512(hash)) => {
+                Some(cache_dir.join(hash))
+            }
+            _ => None,
+        };
+        // entries are trusted to match their name, so they're filled from a valid artifact only
+        if let Some(cached) = &cached {
+            if !cached.exists() && !self.validate(pool).await? {
+                return Err(crate::Error::ChecksumMismatch(self.local_path.clone()));
+            }
         }
+        let archive_path = self.local_path.clone();
+        let span = trace_span!(
+            "extract_natives",
+            ?archive_path,
+            ?extract_dir,
+            entries = field::Empty
+        );
+        pool.run(move || -> crate::Result<()> {
+            let _enter = span.enter();
+            let extracted = match cached {
+                Some(cached) => {
+                    if cached.exists() {
+                        trace!(?cached, "Reusing cached natives");
+                    } else {
+                        // extracted aside and renamed, so the cache never has a partial entry
+                        let cache_dir = cached.parent().expect("cache entries are in a dir");
+                        std::fs::create_dir_all(cache_dir)?;
+                        let (partial, ()) = create_temp_in(cache_dir, ".partial", |path| {
+                            std::fs::create_dir(path)
+                        })?;
+                        let result = extract_archive(&archive_path, &partial).and_then(|_| {
+                            match std::fs::rename(&partial, &cached) {
+                                // extracted by another process meanwhile
+                                Err(_) if cached.exists() => Ok(()),
+                                result => Ok(result?),
+                            }
+                        });
+                        if partial.exists() {
+                            std::fs::remove_dir_all(&partial)?;
+                        }
+                        result?;
+                    }
+                    // links are resolved relative to their own dir otherwise
+                    link_tree(&std::fs::canonicalize(&cached)?, &extract_dir)?
+                }
+                None => extract_archive(&archive_path, &extract_dir)?,
+            };
+            span.record("entries", extracted);
+            Ok(())
+        })
+        .await??;
         Ok(())
     }
 }
 
+/// Extracts every entry of the archive into `extract_dir`, returning the number of files.
+fn extract_archive(archive_path: &Path, extract_dir: &Path) -> crate::Result<usize> {
+    let mut extracted = 0;
+    let filebuf = std::fs::read(archive_path)?;
+    let mut native_artifact = ZipArchive::new(Cursor::new(filebuf))?;
+    // checked upfront, so nothing is extracted from a malicious archive
+    for name in native_artifact.file_names() {
+        if !is_enclosed(name) {
+            return Err(crate::Error::UnsafeArchiveEntry {
+                archive: archive_path.to_path_buf(),
+                entry: name.to_owned(),
+            });
+        }
+    }
+    for i in 0..native_artifact.len() {
+        let mut entry = native_artifact.by_index(i)?;
+        let output_path = extract_dir.join(entry.mangled_name());
+        if entry.is_dir() {
+            std::fs::create_dir_all(&output_path)?;
+            continue;
+        }
+        let mut content = Vec::with_capacity(entry.size() as usize);
+        io::copy(&mut entry, &mut content)?;
+        warn_overwrite(&output_path, &content, archive_path);
+        if let Some(parent) = output_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(output_path, content)?;
+        extracted += 1;
+    }
+    Ok(extracted)
+}
+
+/// Other libraries may ship a native with the same name, e.g. lwjgl modules.
+fn warn_overwrite(output_path: &Path, content: &[u8], archive_path: &Path) {
+    if let Ok(existing) = std::fs::read(output_path) {
+        if existing != content {
+            warn!(
+                ?output_path,
+                ?archive_path,
+                "Native overwrites a different file with the same name"
+            );
+        }
+    }
+}
+
+/// Links every file under `source` into `dest`, copying where links aren't supported. Returns
+/// the number of files.
+fn link_tree(source: &Path, dest: &Path) -> io::Result<usize> {
+    std::fs::create_dir_all(dest)?;
+    let mut linked = 0;
+    for entry in std::fs::read_dir(source)? {
+        let entry = entry?;
+        let (source, dest) = (entry.path(), dest.join(entry.file_name()));
+        if entry.file_type()?.is_dir() {
+            linked += link_tree(&source, &dest)?;
+            continue;
+        }
+        // dangling links exist as well
+        if dest.symlink_metadata().is_ok() {
+            warn_overwrite(&dest, &std::fs::read(&source)?, &source);
+            std::fs::remove_file(&dest)?;
+        }
+        if let Err(e) = symlink_file(&source, &dest) {
+            trace!(%e, ?dest, "Linking isn't supported, copying");
+            std::fs::copy(&source, &dest)?;
+        }
+        linked += 1;
+    }
+    Ok(linked)
+}
+
+#[cfg(unix)]
+fn symlink_file(original: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(original, link)
+}
+
+#[cfg(windows)]
+fn symlink_file(original: &Path, link: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(original, link)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn symlink_file(_original: &Path, _link: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "symlinks aren't supported",
+    ))
+}
+
+#[derive(Debug, Clone)]
+pub struct FetchOptions {
+    /// Platform libraries and natives are selected for.
+    pub target: Target,
+    /// Fail with [`crate::Error::CorruptAssetIndex`] instead of a warning when the sum of asset
+    /// sizes doesn't match the declared total, and with [`crate::Error::AssetIndexMismatch`]
+    /// when the asset index id isn't the version's `assets`.
+    pub strict_asset_index: bool,
+    pub skip_assets: bool,
+    /// Only assets whose path (e.g. `minecraft/lang/de_de.json`) starts with one of the prefixes
+    /// are tracked, all of them if `None`.
+    pub asset_prefixes: Option<Vec<String>>,
+    pub skip_libraries: bool,
+    pub skip_logging: bool,
+    /// Expected sha1 of the version json, as listed by the manifest. A cached `info.json` not
+    /// matching it is downloaded again (requires the `sha1` feature).
+    pub info_sha1: Option<String>,
+    /// Download the version json even if it's saved already, e.g. for re-published snapshots.
+    /// Otherwise a saved one is used without network access (unless mismatching `info_sha1`).
+    pub refresh_metadata: bool,
+    /// Mirrors the canonical hosts of all downloads are moved to.
+    pub hosts: Hosts,
+    /// Natives are extracted once into a subdir of this one named by the artifact's hash, and
+    /// linked (copied, if links aren't supported) into the natives dir, so versions sharing
+    /// natives share the extracted files.
+    pub natives_cache: Option<PathBuf>,
+    /// Pool files are hashed and natives extracted in.
+    pub blocking_pool: BlockingPool,
+}
+
+impl Default for FetchOptions {
+    fn default() -> Self {
+        Self {
+            target: Target::current(),
+            strict_asset_index: false,
+            skip_assets: false,
+            asset_prefixes: None,
+            skip_libraries: false,
+            skip_logging: false,
+            info_sha1: None,
+            refresh_metadata: false,
+            hosts: Hosts::default(),
+            natives_cache: None,
+            blocking_pool: BlockingPool::default(),
+        }
+    }
+}
+
 pub struct RemoteRepository {
     info: VersionInfo,
     indices: Vec<Index>,
+    server: Option<Index>,
+    mappings: Vec<Index>,
+    natives_dir: PathBuf,
+    clean_natives: bool,
+    pool: BlockingPool,
+}
+
+/// Files validated by [`RemoteRepository::track_invalid_with_state`], persisted between sessions
+/// so they aren't hashed again. An entry is trusted while the file's size and modification time
+/// stay the same, the remote checksum doesn't change and it was validated at the current
+/// [`ValidationLevel`] or a stronger one.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ValidationState {
+    files: HashMap<PathBuf, ValidatedFile>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+struct ValidatedFile {
+    checksum: String,
+    size: u64,
+    modified: SystemTime,
+    /// Checks the file passed, e.g. a size-only entry doesn't spare hashing with `sha1` enabled.
+    level: ValidationLevel,
+}
+
+impl ValidatedFile {
+    /// Whether the `recorded` entry vouches for the file as it's now.
+    fn is_covered_by(&self, recorded: &Self) -> bool {
+        recorded.level >= self.level
+            && recorded.checksum == self.checksum
+            && recorded.size == self.size
+            && recorded.modified == self.modified
+    }
+}
+
+impl ValidationState {
+    /// Reads the state from `path`, an empty one is returned if it doesn't exist yet.
+    #[instrument]
+    pub async fn load(path: &Path) -> crate::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let filebuf = fs::read(path).await?;
+        Ok(serde_json::from_slice(&filebuf)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?)
+    }
+
+    #[instrument(skip(self))]
+    pub async fn save(&self, path: &Path) -> crate::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        let filebuf =
+            serde_json::to_vec(self).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(path, filebuf).await?;
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        self.files.clear();
+    }
+}
+
+/// Summary of [`TrackedIndices::pull`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PullReport {
+    pub downloaded: usize,
+    /// Files checked while tracking and found valid, so they weren't pulled.
+    pub skipped: usize,
+    /// Always zero for [`TrackedIndices::pull`], which stops at the first failure.
+    pub failed: usize,
+    /// Declared size of the downloaded files.
+    pub bytes: u64,
+}
+
+/// File [`TrackedIndices::pull_continue_on_error`] failed to pull.
+#[derive(Debug)]
+pub struct PullFailure {
+    pub path: PathBuf,
+    pub error: crate::Error,
 }
 
 pub struct TrackedIndices<'a> {
     remote: &'a RemoteRepository,
-    tracked: Vec<usize>,
+    tracked: Vec<&'a Index>,
+    /// How many checked files were left untracked as valid.
+    valid: usize,
 }
 
 impl RemoteRepository {
-    #[instrument]
     pub async fn fetch(
         downloader: &Manager,
         hierarchy: &Hierarchy,
         remote: Url,
     ) -> crate::Result<Self> {
-        let info_path = hierarchy.version_dir.join("info.json");
-        if !info_path.exists() {
-            downloader.download_file(remote, &info_path).await?;
-        }
-        let info: VersionInfo = {
-            let filebuf = fs::read(&info_path).await?;
-            serde_json::from_slice(&filebuf)
-                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+        Self::fetch_with_features(downloader, hierarchy, remote, &HashMap::new()).await
+    }
+
+    /// Same as [`Self::fetch`], but libraries are filtered by rules against given features.
+    pub async fn fetch_with_features(
+        downloader: &Manager,
+        hierarchy: &Hierarchy,
+        remote: Url,
+        features: &HashMap<&str, bool>,
+    ) -> crate::Result<Self> {
+        Self::fetch_with_options(
+            downloader,
+            hierarchy,
+            remote,
+            features,
+            &FetchOptions::default(),
+        )
+        .await
+    }
+
+    /// Same as [`Self::fetch_with_features`], but libraries and natives are selected for
+    /// `target` instead of the host platform.
+    pub async fn fetch_for(
+        downloader: &Manager,
+        hierarchy: &Hierarchy,
+        remote: Url,
+        target: &Target,
+        features: &HashMap<&str, bool>,
+    ) -> crate::Result<Self> {
+        let options = FetchOptions {
+            target: target.clone(),
+            ..Default::default()
         };
+        Self::fetch_with_options(downloader, hierarchy, remote, features, &options).await
+    }
 
-        let asset_index_path = hierarchy
-            .assets_dir
-            .join(format!("indexes/{}.json", info.assets));
-        let asset_index = Index {
-            metadata: RemoteMetadata::from(&info.asset_index.resource),
-            local_path: asset_index_path.clone(),
+    #[instrument]
+    pub async fn fetch_with_options(
+        downloader: &Manager,
+        hierarchy: &Hierarchy,
+        remote: Url,
+        features: &HashMap<&str, bool>,
+        options: &FetchOptions,
+    ) -> crate::Result<Self> {
+        let info_path = VersionInfo::path(hierarchy);
+        let info_index = Index {
+            category: IndexCategory::Metadata,
+            metadata: RemoteMetadata {
+                url: options.hosts.rewrite(&remote)?,
+                size: 0,
+                checksum: options
+                    .info_sha1
+                    .clone()
+                    .map(Checksum::Sha1)
+                    .unwrap_or(Checksum::None),
+            },
+            local_path: info_path.clone(),
             itype: IndexType::GameFile,
         };
-        asset_index.pull(downloader).await?;
-        let asset_index: AssetIndex = {
-            let filebuf = fs::read(&asset_index_path).await?;
-            serde_json::from_slice(&filebuf)
-                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
-        };
+        let pulled = options.refresh_metadata || !info_path.exists();
+        if pulled {
+            info_index.pull(downloader, &options.blocking_pool).await?;
+        }
+        #[cfg(feature = "sha1")]
+        {
+            let verify = || {
+                info_index
+                    .metadata
+                    .checksum
+                    .verify_in(info_path.clone(), &options.blocking_pool)
+            };
+            let mut valid = verify().await?;
+            // a fresh download mismatching is the remote's fault, pulling again won't help
+            if !valid && !pulled {
+                trace!("Version json mismatches manifest, downloading again");
+                info_index.pull(downloader, &options.blocking_pool).await?;
+                valid = verify().await?;
+            }
+            if !valid {
+                return Err(crate::Error::ChecksumMismatch(info_path));
+            }
+        }
+        let info = VersionInfo::load(&info_path).await?;
+        Self::from_version_info(downloader, hierarchy, info, features, options).await
+    }
 
-        // should be 'nuff
-        let mut indices = Vec::with_capacity(asset_index.objects.len() + info.libraries.len() + 2);
+    /// Same as [`Self::fetch_with_options`], but for version info obtained elsewhere, e.g. merged
+    /// with a mod loader profile. Only the asset index may be downloaded.
+    #[instrument(skip(info))]
+    pub async fn from_version_info(
+        downloader: &Manager,
+        hierarchy: &Hierarchy,
+        mut info: VersionInfo,
+        features: &HashMap<&str, bool>,
+        options: &FetchOptions,
+    ) -> crate::Result<Self> {
+        info.resolve_maven_artifacts()?;
+        let target = &options.target;
+        let mut indices = Vec::new();
 
         // assets
-        let is_legacy_assets = asset_index.map_to_resources.unwrap_or(false);
-        for (path, metadata @ AssetMetadata { hash, size }) in &asset_index.objects {
-            indices.push(Index {
-                metadata: RemoteMetadata {
-                    url: get_asset_url(metadata),
-                    size: *size,
-                },
-                local_path: hierarchy.assets_dir.join(if is_legacy_assets {
-                    format!("virtual/legacy/{}", path)
-                } else {
-                    format!("objects/{}/{}", &hash[..2], &hash)
-                }),
-                itype: IndexType::GameFile,
-            });
+        if !options.skip_assets {
+            indices.extend(Self::asset_indices(downloader, hierarchy, &info, options).await?);
         }
 
         // libraries
-        for lib in &info.libraries {
-            if lib.is_supported_by_rules() {
-                let resources = &lib.resources;
-                if let Some(artifact) = &resources.artifact {
-                    indices.push(Index {
-                        metadata: RemoteMetadata::from(&artifact.resource),
-                        local_path: hierarchy.libraries_dir.join(&artifact.path),
-                        itype: IndexType::GameFile,
-                    });
-                }
-                if let Some(native_artifact) = resources.get_native_for_os() {
-                    indices.push(Index {
-                        metadata: RemoteMetadata::from(&native_artifact.resource),
-                        local_path: hierarchy.libraries_dir.join(&native_artifact.path),
-                        itype: IndexType::NativeArtifact {
-                            extract_dir: hierarchy.natives_dir.to_path_buf(),
-                        },
-                    });
+        if !options.skip_libraries {
+            for lib in &info.libraries {
+                if lib.is_allowed_for(target, features) {
+                    let resources = &lib.resources;
+                    if let Some(artifact) = &resources.artifact {
+                        indices.push(Index {
+                            category: IndexCategory::Library,
+                            metadata: RemoteMetadata::from(&artifact.resource),
+                            local_path: hierarchy.libraries_dir.join(&artifact.path),
+                            itype: IndexType::GameFile,
+                        });
+                    }
+                    // natives-only libraries have no artifact, but are tracked all the same
+                    let native_artifact = lib.get_native_for(target);
+                    if let Some(native_artifact) = native_artifact {
+                        indices.push(Index {
+                            category: IndexCategory::Native,
+                            metadata: RemoteMetadata::from(&native_artifact.resource),
+                            local_path: hierarchy.libraries_dir.join(&native_artifact.path),
+                            itype: IndexType::NativeArtifact {
+                                extract_dir: hierarchy.natives_dir.to_path_buf(),
+                                cache_dir: options.natives_cache.clone(),
+                            },
+                        });
+                    } else if let Some(classifier) = lib.native_classifier_for(target) {
+                        warn!(library = %lib.name, classifier, "Declared native isn't downloadable");
+                    } else if resources.artifact.is_none() {
+                        trace!(library = %lib.name, "Library has nothing to download for target");
+                    }
                 }
             }
         }
 
         // client and other
         indices.push(Index {
+            category: IndexCategory::Client,
             metadata: RemoteMetadata::from(&info.downloads.client),
             local_path: hierarchy.version_dir.join("client.jar"),
             itype: IndexType::GameFile,
         });
-        if let Some(logging) = &info.logging {
+        if let Some(logging) = info.logging.as_ref().filter(|_| !options.skip_logging) {
             indices.push(Index {
+                category: IndexCategory::Logging,
                 metadata: RemoteMetadata::from(&logging.client.config.resource),
                 local_path: hierarchy.version_dir.join(&logging.client.config.id),
                 itype: IndexType::GameFile,
             });
         }
 
-        Ok(Self { info, indices })
+        let server = info.downloads.server.as_ref().map(|server| Index {
+            category: IndexCategory::Server,
+            metadata: RemoteMetadata::from(server),
+            local_path: hierarchy.version_dir.join("server.jar"),
+            itype: IndexType::GameFile,
+        });
+
+        let mappings = [
+            (&info.downloads.client_mappings, "client_mappings.txt"),
+            (&info.downloads.server_mappings, "server_mappings.txt"),
+        ]
+        .into_iter()
+        .filter_map(|(resource, filename)| {
+            resource.as_ref().map(|resource| Index {
+                category: IndexCategory::Mappings,
+                metadata: RemoteMetadata::from(resource),
+                local_path: hierarchy.version_dir.join(filename),
+                itype: IndexType::GameFile,
+            })
+        })
+        .collect::<Vec<_>>();
+
+        let mut repository = Self {
+            info,
+            indices,
+            server,
+            mappings,
+            natives_dir: hierarchy.natives_dir.clone(),
+            clean_natives: true,
+            pool: options.blocking_pool.clone(),
+        };
+        if options.hosts != Hosts::default() {
+            for index in repository
+                .indices
+                .iter_mut()
+                .chain(repository.server.iter_mut())
+                .chain(repository.mappings.iter_mut())
+            {
+                index.metadata.url = options.hosts.rewrite(&index.metadata.url)?;
+            }
+        }
+        Ok(repository)
+    }
+
+    #[instrument(skip(info))]
+    async fn asset_indices(
+        downloader: &Manager,
+        hierarchy: &Hierarchy,
+        info: &VersionInfo,
+        options: &FetchOptions,
+    ) -> crate::Result<Vec<Index>> {
+        if let Err(e) = info.check_asset_index() {
+            if options.strict_asset_index {
+                return Err(e);
+            }
+            warn!(%e, "Inconsistent asset index metadata");
+        }
+        let asset_index_path = hierarchy
+            .assets_dir
+            .join(format!("indexes/{}.json", info.assets));
+        let mut asset_index_metadata = RemoteMetadata::from(&info.asset_index.resource);
+        asset_index_metadata.url = options.hosts.rewrite(&asset_index_metadata.url)?;
+        let asset_index = Index {
+            category: IndexCategory::Metadata,
+            metadata: asset_index_metadata,
+            local_path: asset_index_path.clone(),
+            itype: IndexType::GameFile,
+        };
+        if !asset_index.validate(&options.blocking_pool).await? {
+            asset_index.pull(downloader, &options.blocking_pool).await?;
+        }
+        let asset_index: AssetIndex = {
+            let filebuf = fs::read(&asset_index_path).await?;
+            serde_json::from_slice(&filebuf).map_err(|source| crate::Error::AssetIndexParse {
+                id: info.assets.clone(),
+                path: asset_index_path.clone(),
+                source,
+            })?
+        };
+        let objects_size: u64 = asset_index.objects.values().map(|obj| obj.size).sum();
+        if objects_size != info.asset_index.total_size {
+            if options.strict_asset_index {
+                return Err(crate::Error::CorruptAssetIndex {
+                    expected: info.asset_index.total_size,
+                    actual: objects_size,
+                });
+            }
+            warn!(
+                expected = info.asset_index.total_size,
+                actual = objects_size,
+                "Asset index total size mismatch"
+            );
+        }
+
+        let mut indices = Vec::with_capacity(asset_index.objects.len());
+        let maps_to_resources = asset_index.maps_to_resources();
+        let is_legacy_assets = maps_to_resources || asset_index.is_virtual();
+        let mut unique_hashes = HashSet::with_capacity(asset_index.objects.len());
+        for (path, metadata @ AssetMetadata { hash, size }) in &asset_index.objects {
+            if !metadata.has_hash() {
+                trace!(path, "Skipping asset without hash");
+                continue;
+            }
+            if let Some(prefixes) = &options.asset_prefixes {
+                if !prefixes
+                    .iter()
+                    .any(|prefix| path.starts_with(prefix.as_str()))
+                {
+                    continue;
+                }
+            }
+            // objects are addressed by hash, so the same content is pulled only once
+            if !is_legacy_assets && !unique_hashes.insert(hash) {
+                continue;
+            }
+            indices.push(Index {
+                category: IndexCategory::Asset,
+                metadata: RemoteMetadata {
+                    url: get_asset_url(metadata)?,
+                    size: *size,
+                    checksum: Checksum::Sha1(hash.clone()),
+                },
+                local_path: if maps_to_resources {
+                    join_enclosed(&hierarchy.resources_dir(), path)?
+                } else {
+                    asset_object_path(&hierarchy.assets_dir, path, metadata, is_legacy_assets)?
+                },
+                itype: IndexType::GameFile,
+            });
+        }
+
+        Ok(indices)
+    }
+
+    /// Fetches a version listed by the manifest, verifying its json against the manifest's sha1.
+    pub async fn fetch_version(
+        downloader: &Manager,
+        hierarchy: &Hierarchy,
+        version: &Version,
+        features: &HashMap<&str, bool>,
+    ) -> crate::Result<Self> {
+        let options = FetchOptions {
+            info_sha1: version.sha1.clone(),
+            ..Default::default()
+        };
+        Self::fetch_with_options(
+            downloader,
+            hierarchy,
+            version.url.clone(),
+            features,
+            &options,
+        )
+        .await
+    }
+
+    pub fn indices(&self) -> impl Iterator<Item = IndexInfo<'_>> {
+        self.indices.iter().map(Index::info)
+    }
+
+    /// Native artifacts, each with the dir it's extracted to.
+    pub fn natives(&self) -> impl Iterator<Item = IndexInfo<'_>> {
+        self.indices
+            .iter()
+            .filter(|index| index.extract_dir().is_some())
+            .map(Index::info)
     }
 
     pub fn version_info(&self) -> &VersionInfo {
         &self.info
     }
 
+    pub fn into_version_info(self) -> VersionInfo {
+        self.info
+    }
+
+    /// Directory where natives of this version are extracted to.
+    pub fn natives_dir(&self) -> &Path {
+        &self.natives_dir
+    }
+
+    /// Whether natives directories are cleared before extraction (enabled by default).
+    /// Disable it when several versions share the same natives directory.
+    pub fn set_clean_natives(&mut self, clean: bool) {
+        self.clean_natives = clean;
+    }
+
     pub fn bytes_size(&self) -> u64 {
         self.indices.iter().map(|i| i.metadata.size).sum()
     }
 
-    #[instrument(skip(self))]
-    pub fn track_all(&self) -> TrackedIndices {
-        TrackedIndices {
-            remote: self,
-            tracked: (0..self.indices.len()).collect(),
+    /// Adds files of `modpack` supported on `side`, placed relative to `game_dir`, so they're
+    /// validated and pulled along with the game files. Only the first of the file's downloads is
+    /// used.
+    #[instrument(skip(self, modpack))]
+    pub fn add_modpack(
+        &mut self,
+        modpack: &Modpack,
+        game_dir: &Path,
+        side: Side,
+    ) -> crate::Result<()> {
+        let indices = modpack
+            .index()
+            .files_for(side)
+            .map(|file| {
+                let path = file.relative_path().ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("modpack file path {} is unsafe", file.path),
+                    )
+                })?;
+                let url = file.downloads.first().ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("modpack file {} has no downloads", file.path),
+                    )
+                })?;
+                Ok(Index {
+                    category: IndexCategory::Modpack,
+                    metadata: RemoteMetadata {
+                        url: url.clone(),
+                        size: file.file_size,
+                        checksum: file.checksum(),
+                    },
+                    local_path: game_dir.join(path),
+                    itype: IndexType::GameFile,
+                })
+            })
+            .collect::<crate::Result<Vec<_>>>()?;
+        trace!(count = indices.len(), "Adding modpack files");
+        self.indices.extend(indices);
+        Ok(())
+    }
+
+    fn natives_missing(&self) -> bool {
+        self.indices
+            .iter()
+            .filter_map(Index::extract_dir)
+            .any(|extract_dir| !extract_dir.exists())
+    }
+
+    async fn remove_natives(&self) -> crate::Result<()> {
+        let mut extract_dirs: Vec<_> = self.indices.iter().filter_map(Index::extract_dir).collect();
+        extract_dirs.sort();
+        extract_dirs.dedup();
+        for extract_dir in extract_dirs {
+            if extract_dir.exists() {
+                trace!(?extract_dir, "Removing stale natives");
+                fs::remove_dir_all(extract_dir).await?;
+            }
         }
+        Ok(())
     }
 
+    /// Extracts natives again from the artifacts already in the libraries dir, without network
+    /// access. The natives dir is cleared first, unless disabled with
+    /// [`Self::set_clean_natives`]. Fails before touching it if an artifact is missing or invalid.
     #[instrument(skip(self))]
-    pub async fn track_invalid(&self) -> crate::Result<TrackedIndices<'_>> {
-        let mut tracked = Vec::with_capacity(self.indices.len());
-        for (i, index) in self.indices.iter().enumerate() {
-            if !index.validate().await? {
-                tracked.push(i);
+    pub async fn extract_natives(&self) -> crate::Result<()> {
+        let natives: Vec<_> = self
+            .indices
+            .iter()
+            .filter(|index| index.extract_dir().is_some())
+            .collect();
+        for index in &natives {
+            if !index.validate(&self.pool).await? {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!(
+                        "native artifact {} is missing or invalid",
+                        index.local_path.display()
+                    ),
+                )
+                .into());
             }
         }
+        if self.clean_natives {
+            self.remove_natives().await?;
+        }
+        for index in natives {
+            index.extract(&self.pool).await?;
+        }
+        Ok(())
+    }
 
-        Ok(TrackedIndices {
-            remote: self,
-            tracked,
-        })
+    /// Validates and pulls invalid files in a single pass, so validation of later files overlaps
+    /// downloads of earlier ones. Ends up in the same state as `track_invalid` followed by `pull`.
+    /// Returns how many files were pulled.
+    #[instrument(skip(self))]
+    pub async fn sync(&self, downloader: &Manager, concurrency: usize) -> crate::Result<usize> {
+        warn_weak_validation();
+        // natives are settled upfront, so the dir is cleared only if some artifact is invalid
+        let mut invalid_natives = HashSet::new();
+        for index in self.indices.iter().filter(|i| i.extract_dir().is_some()) {
+            if !index.validate(&self.pool).await? {
+                invalid_natives.insert(index.local_path.as_path());
+            }
+        }
+        let natives_missing = self.natives_missing();
+        let cleaned = self.clean_natives && !invalid_natives.is_empty();
+        if cleaned {
+            self.remove_natives().await?;
+        }
+        if cleaned || natives_missing {
+            for index in self.indices.iter().filter(|index| {
+                index.extract_dir().is_some()
+                    && !invalid_natives.contains(index.local_path.as_path())
+            }) {
+                index.extract(&self.pool).await?;
+            }
+        }
+        let mut ordered: Vec<_> = self.indices.iter().collect();
+        ordered.sort_by_key(|index| !index.category.is_critical());
+        let invalid_natives = &invalid_natives;
+        stream::iter(ordered)
+            .map(|index| async move {
+                let valid = if index.extract_dir().is_some() {
+                    !invalid_natives.contains(index.local_path.as_path())
+                } else {
+                    index.validate(&self.pool).await?
+                };
+                if valid {
+                    Ok(0)
+                } else {
+                    index.pull(downloader, &self.pool).await?;
+                    Ok(1)
+                }
+            })
+            .buffer_unordered(concurrency)
+            .try_fold(0, |pulled, n| async move { Ok(pulled + n) })
+            .await
     }
-}
 
-impl TrackedIndices<'_> {
-    fn indices(&self) -> impl Iterator<Item = &Index> {
-        self.tracked
+    /// Removes partial downloads left by interrupted pulls of this repository's files, returning
+    /// the removed paths. Only `<file>.part` siblings of managed files are touched.
+    #[instrument(skip(self))]
+    pub async fn clean_partial(&self) -> crate::Result<Vec<PathBuf>> {
+        let mut removed = Vec::new();
+        for index in self
+            .indices
             .iter()
-            .copied()
-            .map(|i| &self.remote.indices[i])
+            .chain(&self.server)
+            .chain(&self.mappings)
+        {
+            let partial = partial_path(&index.local_path);
+            if partial.exists() {
+                trace!(?partial, "Removing partial download");
+                fs::remove_file(&partial).await?;
+                removed.push(partial);
+            }
+        }
+        Ok(removed)
     }
 
-    pub fn bytes_size(&self) -> u64 {
-        self.indices().map(|i| i.metadata.size).sum()
+    #[instrument(skip(self))]
+    pub fn track_all(&self) -> TrackedIndices<'_> {
+        TrackedIndices {
+            remote: self,
+            tracked: self.indices.iter().collect(),
+            valid: 0,
+        }
     }
 
+    /// Tracks `server.jar` separately from the client files, so it's pulled only on demand.
+    /// Nothing is tracked if the version has no server distribution.
     #[instrument(skip(self))]
-    pub async fn pull(&self, downloader: &Manager, concurrency: usize) -> crate::Result<()> {
-        stream::iter(self.indices())
-            .map(Ok)
-            .try_for_each_concurrent(concurrency, |index| index.pull(downloader))
-            .await
+    pub fn track_server(&self) -> TrackedIndices<'_> {
+        TrackedIndices {
+            remote: self,
+            tracked: self.server.iter().collect(),
+            valid: 0,
+        }
+    }
+
+    /// Tracks obfuscation mappings of the client and the server, whichever are present.
+    #[instrument(skip(self))]
+    pub fn track_mappings(&self) -> TrackedIndices<'_> {
+        TrackedIndices {
+            remote: self,
+            tracked: self.mappings.iter().collect(),
+            valid: 0,
+        }
+    }
+
+    /// Re-downloads asset objects that are missing or corrupted (checksums are verified with the
+    /// `sha1` feature only), returning how many were repaired.
+    #[instrument(skip(self))]
+    pub async fn repair_assets(
+        &self,
+        downloader: &Manager,
+        concurrency: usize,
+    ) -> crate::Result<usize> {
+        let mut tracked = Vec::new();
+        let mut valid = 0;
+        for index in self
+            .indices
+            .iter()
+            .filter(|index| index.category == IndexCategory::Asset)
+        {
+            if index.validate(&self.pool).await? {
+                valid += 1;
+            } else {
+                tracked.push(index);
+            }
+        }
+        let repaired = tracked.len();
+        TrackedIndices {
+            remote: self,
+            tracked,
+            valid,
+        }
+        .pull(downloader, concurrency)
+        .await?;
+        Ok(repaired)
+    }
+
+    /// Same as [`Self::track_invalid`], but files recorded in `state` as valid are skipped
+    /// without hashing, and newly validated files are recorded. Files pulled afterwards get
+    /// recorded on the next call.
+    #[instrument(skip(self, state))]
+    pub async fn track_invalid_with_state(
+        &self,
+        state: &mut ValidationState,
+    ) -> crate::Result<TrackedIndices<'_>> {
+        warn_weak_validation();
+        let mut tracked = Vec::with_capacity(self.indices.len());
+        for index in &self.indices {
+            if let Some(recorded) = state.files.get(&index.local_path) {
+                if let Ok(metadata) = fs::metadata(&index.local_path).await {
+                    if let Some(current) = index.validated_file(&metadata) {
+                        if current.is_covered_by(recorded) {
+                            continue;
+                        }
+                    }
+                }
+            }
+            if index.validate(&self.pool).await? {
+                let metadata = fs::metadata(&index.local_path).await?;
+                if let Some(validated) = index.validated_file(&metadata) {
+                    state.files.insert(index.local_path.clone(), validated);
+                }
+            } else {
+                state.files.remove(&index.local_path);
+                tracked.push(index);
+            }
+        }
+
+        Ok(TrackedIndices {
+            remote: self,
+            valid: self.indices.len() - tracked.len(),
+            tracked,
+        })
+    }
+
+    /// Tracks files failing validation, see [`ValidationLevel::current`] for the checks made.
+    #[instrument(skip(self))]
+    pub async fn track_invalid(&self) -> crate::Result<TrackedIndices<'_>> {
+        warn_weak_validation();
+        let mut tracked = Vec::with_capacity(self.indices.len());
+        for index in &self.indices {
+            if !index.validate(&self.pool).await? {
+                tracked.push(index);
+            }
+        }
+
+        Ok(TrackedIndices {
+            remote: self,
+            valid: self.indices.len() - tracked.len(),
+            tracked,
+        })
+    }
+}
+
+impl TrackedIndices<'_> {
+    fn indices(&self) -> impl Iterator<Item = &Index> {
+        self.tracked.iter().copied()
+    }
+
+    pub fn bytes_size(&self) -> u64 {
+        self.indices().map(|i| i.metadata.size).sum()
+    }
+
+    /// Tracked files, for downloading them with an engine of your own. Call
+    /// [`Self::complete_external`] once they're in place.
+    pub fn iter(&self) -> impl Iterator<Item = IndexInfo<'_>> {
+        self.indices().map(Index::info)
+    }
+
+    /// Finishes a pull done outside of the crate: files are validated and natives extracted, as
+    /// [`Self::pull`] would. Returns files that are still missing or invalid.
+    #[instrument(skip(self))]
+    pub async fn complete_external(&self, concurrency: usize) -> crate::Result<Vec<IndexInfo<'_>>> {
+        self.prepare_natives().await?;
+        let invalid: Vec<_> = stream::iter(self.indices())
+            .map(|index| async move {
+                if !index.validate(&self.remote.pool).await? {
+                    return crate::Result::Ok(Some(index.info()));
+                }
+                index.extract(&self.remote.pool).await?;
+                Ok(None)
+            })
+            .buffer_unordered(concurrency)
+            .try_filter_map(|invalid| async move { Ok(invalid) })
+            .try_collect()
+            .await?;
+        trace!(invalid = invalid.len(), "Completed external pull");
+        Ok(invalid)
+    }
+
+    /// Same as [`Self::bytes_size`], but sizes not declared by metadata are requested from the
+    /// remote with `HEAD` requests. Opt-in, as it costs a round trip per such file.
+    #[instrument(skip(self))]
+    pub async fn probe_bytes_size(
+        &self,
+        downloader: &Manager,
+        concurrency: usize,
+    ) -> crate::Result<u64> {
+        stream::iter(self.indices())
+            .map(|index| async move {
+                if index.metadata.size != 0 {
+                    return Ok(index.metadata.size);
+                }
+                let len = downloader
+                    .content_length(index.metadata.url.clone())
+                    .await?;
+                trace!(?len, url = %index.metadata.url, "Probed size");
+                Ok(len.unwrap_or(0))
+            })
+            .buffer_unordered(concurrency)
+            .try_fold(0, |total, size| async move { Ok(total + size) })
+            .await
+    }
+
+    #[instrument(skip(self))]
+    async fn clean_natives(&self) -> crate::Result<bool> {
+        if !self.remote.clean_natives || !self.indices().any(|i| i.extract_dir().is_some()) {
+            return Ok(false);
+        }
+        self.remote.remove_natives().await?;
+        Ok(true)
+    }
+
+    /// Clears the natives dir if natives are tracked, then extracts natives of untracked
+    /// artifacts right away, so a pull interrupted later doesn't leave the dir partial.
+    async fn prepare_natives(&self) -> crate::Result<()> {
+        // the artifacts are kept in the libraries dir, so a wiped natives dir is restored from them
+        let natives_missing = self.remote.natives_missing();
+        let cleaned = self.clean_natives().await?;
+        if !cleaned && !natives_missing {
+            return Ok(());
+        }
+        // natives of untracked (valid) artifacts are gone too, so extract them again
+        let tracked: HashSet<&Path> = self
+            .indices()
+            .map(|index| index.local_path.as_path())
+            .collect();
+        for index in self.remote.indices.iter().filter(|index| {
+            index.extract_dir().is_some() && !tracked.contains(index.local_path.as_path())
+        }) {
+            // e.g. never pulled on a server host, so there's nothing to restore
+            if index.validate(&self.remote.pool).await? {
+                index.extract(&self.remote.pool).await?;
+            } else {
+                trace!(local_path = ?index.local_path, "Skipping natives of invalid artifact");
+            }
+        }
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    pub async fn pull(
+        &self,
+        downloader: &Manager,
+        concurrency: usize,
+    ) -> crate::Result<PullReport> {
+        self.pull_inner(downloader, concurrency, true)
+            .await
+            .map(|(report, _)| report)
+    }
+
+    /// Same as [`Self::pull`], but a failed file doesn't abort the others. Failures are returned
+    /// to be retried later, e.g. with a fresh [`RemoteRepository::track_invalid`].
+    #[instrument(skip(self))]
+    pub async fn pull_continue_on_error(
+        &self,
+        downloader: &Manager,
+        concurrency: usize,
+    ) -> crate::Result<(PullReport, Vec<PullFailure>)> {
+        self.pull_inner(downloader, concurrency, false).await
+    }
+
+    async fn pull_inner(
+        &self,
+        downloader: &Manager,
+        concurrency: usize,
+        fail_fast: bool,
+    ) -> crate::Result<(PullReport, Vec<PullFailure>)> {
+        self.prepare_natives().await?;
+        // files required to launch go first, assets are pulled after them
+        let mut ordered: Vec<_> = self.indices().collect();
+        ordered.sort_by_key(|index| !index.category.is_critical());
+        let mut report = PullReport {
+            downloaded: ordered.len(),
+            skipped: self.valid,
+            failed: 0,
+            bytes: ordered.iter().map(|index| index.metadata.size).sum(),
+        };
+        let mut failures = Vec::new();
+        let pool = &self.remote.pool;
+        if fail_fast {
+            stream::iter(ordered)
+                .map(Ok)
+                .try_for_each_concurrent(concurrency, |index| index.pull(downloader, pool))
+                .await?;
+        } else {
+            let mut results = stream::iter(ordered)
+                .map(|index| async move { (index, index.pull(downloader, pool).await) })
+                .buffer_unordered(concurrency);
+            while let Some((index, result)) = results.next().await {
+                if let Err(error) = result {
+                    warn!(local_path = ?index.local_path, %error, "Failed to pull");
+                    report.downloaded -= 1;
+                    report.failed += 1;
+                    report.bytes -= index.metadata.size;
+                    failures.push(PullFailure {
+                        path: index.local_path.clone(),
+                        error,
+                    });
+                }
+            }
+        }
+        trace!(?report, "Pulled");
+        Ok((report, failures))
+    }
+
+    /// Same as [`Self::pull`], but stops as soon as `shutdown` resolves, e.g. on
+    /// `tokio::signal::ctrl_c()`. Returns whether the pull completed. Files interrupted midway
+    /// are left partial and get tracked by [`RemoteRepository::track_invalid`] next time.
+    #[instrument(skip(self, shutdown))]
+    pub async fn pull_until<F>(
+        &self,
+        downloader: &Manager,
+        concurrency: usize,
+        shutdown: F,
+    ) -> crate::Result<bool>
+    where
+        F: Future,
+    {
+        let pull = self.pull(downloader, concurrency);
+        pin_mut!(pull, shutdown);
+        match future::select(pull, shutdown).await {
+            Either::Left((result, _)) => result.map(|_| true),
+            Either::Right(_) => {
+                trace!("Pull interrupted by shutdown");
+                Ok(false)
+            }
+        }
+    }
+}
+
+/// Fetches the version and pulls whatever is missing or invalid, the version info is returned
+/// ready for [`GameCommand`](crate::process::GameCommand). Nothing is downloaded when the version
+/// is installed already.
+#[instrument(skip(downloader))]
+pub async fn ensure_installed(
+    downloader: &Manager,
+    hierarchy: &Hierarchy,
+    version: &Version,
+    concurrency: usize,
+) -> crate::Result<VersionInfo> {
+    let repository =
+        RemoteRepository::fetch_version(downloader, hierarchy, version, &HashMap::new()).await?;
+    let pulled = repository.sync(downloader, concurrency).await?;
+    trace!(pulled, "Version installed");
+    Ok(repository.into_version_info())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use serde_json::Value;
+
+    use super::*;
+    use crate::testing::{
+        natives_library_json, spans, temp_dir, version_json, warnings, zip, MockServer,
+    };
+
+    /// Serves `info` along with an empty asset index and fetches it.
+    async fn fetch(server: &MockServer, hierarchy: &Hierarchy, info: &Value) -> RemoteRepository {
+        fetch_with_assets(
+            server,
+            hierarchy,
+            info,
+            &serde_json::json!({ "objects": {} }),
+        )
+        .await
+    }
+
+    async fn fetch_with_assets(
+        server: &MockServer,
+        hierarchy: &Hierarchy,
+        info: &Value,
+        asset_index: &Value,
+    ) -> RemoteRepository {
+        server.set("1.12.2.json", info.to_string());
+        server.set("1.12.json", asset_index.to_string());
+        RemoteRepository::fetch(&Manager::default(), hierarchy, server.url("1.12.2.json"))
+            .await
+            .unwrap()
+    }
+
+    fn asset_paths<'a>(repository: &'a RemoteRepository, hierarchy: &Hierarchy) -> Vec<&'a Path> {
+        let mut paths: Vec<_> = repository
+            .indices
+            .iter()
+            .filter_map(|index| index.local_path.strip_prefix(&hierarchy.assets_dir).ok())
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    /// Version having a single natives library, whose archives contain `liblwjgl.so`. Its
+    /// files are served, except for assets.
+    async fn natives_version(server: &MockServer) -> Value {
+        for os in ["linux", "windows", "macos"] {
+            let archive = zip(&[("liblwjgl.so", b"native")]);
+            server.set(&format!("lwjgl-natives-{}.jar", os), archive);
+        }
+        server.set("client.jar", "client");
+        let mut info = version_json(&server.url(""));
+        info["libraries"] = Value::Array(vec![natives_library_json(&server.url(""), "lwjgl")]);
+        info
+    }
+
+    #[tokio::test]
+    async fn stale_natives_are_removed_before_extraction() {
+        let dir = temp_dir();
+        let hierarchy = Hierarchy::builder(&dir, "1.12.2").build();
+        let server = MockServer::start().await;
+        let repository = fetch(&server, &hierarchy, &natives_version(&server).await).await;
+        assert_eq!(repository.natives_dir(), hierarchy.natives_dir);
+        fs::create_dir_all(&hierarchy.natives_dir).await.unwrap();
+        fs::write(hierarchy.natives_dir.join("stale.so"), b"stale")
+            .await
+            .unwrap();
+
+        repository
+            .track_all()
+            .pull(&Manager::default(), 4)
+            .await
+            .unwrap();
+
+        assert!(!hierarchy.natives_dir.join("stale.so").exists());
+        assert!(hierarchy.natives_dir.join("liblwjgl.so").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn natives_are_kept_when_cleaning_is_disabled() {
+        let dir = temp_dir();
+        let hierarchy = Hierarchy::builder(&dir, "1.12.2").build();
+        let server = MockServer::start().await;
+        let mut repository = fetch(&server, &hierarchy, &natives_version(&server).await).await;
+        repository.set_clean_natives(false);
+        fs::create_dir_all(&hierarchy.natives_dir).await.unwrap();
+        fs::write(hierarchy.natives_dir.join("shared.so"), b"shared")
+            .await
+            .unwrap();
+
+        repository
+            .track_all()
+            .pull(&Manager::default(), 4)
+            .await
+            .unwrap();
+
+        assert!(hierarchy.natives_dir.join("shared.so").exists());
+        assert!(hierarchy.natives_dir.join("liblwjgl.so").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn server_jar_is_tracked_only_when_present() {
+        let dir = temp_dir();
+        let hierarchy = Hierarchy::builder(&dir, "1.12.2").build();
+        let server = MockServer::start().await;
+        let mut info = version_json(&server.url(""));
+
+        let repository = fetch(&server, &hierarchy, &info).await;
+        assert_eq!(repository.track_server().indices().count(), 0);
+
+        info["downloads"]["server"] = serde_json::json!({
+            "sha1": "",
+            "size": 6,
+            "url": server.url("server.jar"),
+        });
+        fs::remove_file(hierarchy.version_dir.join("info.json"))
+            .await
+            .unwrap();
+        let repository = fetch(&server, &hierarchy, &info).await;
+        let tracked = repository.track_server();
+        let paths: Vec<_> = tracked.indices().map(|i| &i.local_path).collect();
+        assert_eq!(paths, [&hierarchy.version_dir.join("server.jar")]);
+        assert_eq!(tracked.bytes_size(), 6);
+        // tracking alone never downloads
+        assert_eq!(server.hits("server.jar"), 0);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn mappings_are_tracked_and_pulled() {
+        let dir = temp_dir();
+        let hierarchy = Hierarchy::builder(&dir, "1.12.2").build();
+        let server = MockServer::start().await;
+        let mut info = version_json(&server.url(""));
+        for side in ["client", "server"] {
+            let path = format!("{}.txt", side);
+            server.set(&path, side);
+            info["downloads"][format!("{}_mappings", side)] = serde_json::json!({
+                "sha1": "",
+                "size": side.len(),
+                "url": server.url(&path),
+            });
+        }
+
+        let repository = fetch(&server, &hierarchy, &info).await;
+        let tracked = repository.track_mappings();
+        assert_eq!(tracked.indices().count(), 2);
+        tracked.pull(&Manager::default(), 2).await.unwrap();
+
+        for side in ["client", "server"] {
+            let path = hierarchy.version_dir.join(format!("{}_mappings.txt", side));
+            assert_eq!(fs::read_to_string(path).await.unwrap(), side);
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn cross_target_fetch_selects_target_natives() {
+        let dir = temp_dir();
+        let hierarchy = Hierarchy::builder(&dir, "1.12.2").build();
+        let server = MockServer::start().await;
+        let mut info = natives_version(&server).await;
+        info["libraries"]
+            .as_array_mut()
+            .unwrap()
+            .push(serde_json::json!({
+                "name": "a:not-on-osx:1.0",
+                "downloads": {
+                    "artifact": {
+                        "path": "not-on-osx.jar",
+                        "sha1": "",
+                        "size": 0,
+                        "url": server.url("not-on-osx.jar"),
+                    },
+                },
+                "rules": [{ "action": "allow" }, { "action": "disallow", "os": { "name": "osx" } }],
+            }));
+        server.set("1.12.2.json", info.to_string());
+        server.set("1.12.json", r#"{"objects": {}}"#);
+        let target = Target {
+            os: "osx".to_owned(),
+            arch: "x86_64".to_owned(),
+            version: None,
+        };
+
+        let repository = RemoteRepository::fetch_for(
+            &Manager::default(),
+            &hierarchy,
+            server.url("1.12.2.json"),
+            &target,
+            &HashMap::new(),
+        )
+        .await
+        .unwrap();
+
+        let libraries: Vec<_> = repository
+            .indices
+            .iter()
+            .filter_map(|index| index.local_path.strip_prefix(&hierarchy.libraries_dir).ok())
+            .collect();
+        assert_eq!(libraries, [Path::new("lwjgl-natives-macos.jar")]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn objects_sharing_a_hash_are_pulled_once() {
+        let dir = temp_dir();
+        let hierarchy = Hierarchy::builder(&dir, "1.12.2").build();
+        let server = MockServer::start().await;
+        let info = version_json(&server.url(""));
+        let hash = "bdf48ef6b5d0d23bbb02e17d04865216179f510a";
+        let objects = serde_json::json!({
+            "minecraft/sounds/a.ogg": { "hash": hash, "size": 4 },
+            "minecraft/sounds/b.ogg": { "hash": hash, "size": 4 },
+        });
+
+        let asset_index = serde_json::json!({ "objects": objects });
+        let repository = fetch_with_assets(&server, &hierarchy, &info, &asset_index).await;
+        assert_eq!(
+            asset_paths(&repository, &hierarchy),
+            [Path::new("objects/bd").join(hash)]
+        );
+
+        // legacy objects are stored by name, so both are kept. The fixture index declares no
+        // size, so the cached one would be taken as valid
+        std::fs::remove_dir_all(hierarchy.assets_dir.join("indexes")).unwrap();
+        let asset_index = serde_json::json!({ "virtual": true, "objects": objects });
+        let repository = fetch_with_assets(&server, &hierarchy, &info, &asset_index).await;
+        assert_eq!(
+            asset_paths(&repository, &hierarchy),
+            [
+                Path::new("virtual/legacy/minecraft/sounds/a.ogg"),
+                Path::new("virtual/legacy/minecraft/sounds/b.ogg")
+            ]
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn asset_index_total_size_is_checked() {
+        let dir = temp_dir();
+        let hierarchy = Hierarchy::builder(&dir, "1.12.2").build();
+        let server = MockServer::start().await;
+        let mut info = version_json(&server.url(""));
+        info["assetIndex"]["totalSize"] = 5.into();
+        server.set("1.12.2.json", info.to_string());
+        let asset_index = serde_json::json!({
+            "objects": { "a.ogg": { "hash": "bdf48ef6b5d0d23bbb02e17d04865216179f510a", "size": 4 } },
+        });
+        server.set("1.12.json", asset_index.to_string());
+        let fetch = |strict_asset_index| {
+            let options = FetchOptions {
+                strict_asset_index,
+                ..Default::default()
+            };
+            let (hierarchy, url) = (&hierarchy, server.url("1.12.2.json"));
+            async move {
+                RemoteRepository::fetch_with_options(
+                    &Manager::default(),
+                    hierarchy,
+                    url,
+                    &HashMap::new(),
+                    &options,
+                )
+                .await
+            }
+        };
+
+        assert!(fetch(false).await.is_ok());
+        assert!(matches!(
+            fetch(true).await,
+            Err(crate::Error::CorruptAssetIndex {
+                expected: 5,
+                actual: 4
+            })
+        ));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn assets_without_hash_are_excluded() {
+        let dir = temp_dir();
+        let hierarchy = Hierarchy::builder(&dir, "1.12.2").build();
+        let server = MockServer::start().await;
+        let info = version_json(&server.url(""));
+        let hash = "bdf48ef6b5d0d23bbb02e17d04865216179f510a";
+        let asset_index = serde_json::json!({
+            "objects": {
+                "hashed.ogg": { "hash": hash, "size": 0 },
+                "unhashed.ogg": { "size": 0 },
+            },
+        });
+
+        let repository = fetch_with_assets(&server, &hierarchy, &info, &asset_index).await;
+        assert_eq!(
+            asset_paths(&repository, &hierarchy),
+            [Path::new("objects/bd").join(hash)]
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn skipped_assets_are_not_tracked() {
+        let dir = temp_dir();
+        let hierarchy = Hierarchy::builder(&dir, "1.12.2").build();
+        let server = MockServer::start().await;
+        server.set("1.12.2.json", natives_version(&server).await.to_string());
+        let options = FetchOptions {
+            skip_assets: true,
+            ..Default::default()
+        };
+
+        let repository = RemoteRepository::fetch_with_options(
+            &Manager::default(),
+            &hierarchy,
+            server.url("1.12.2.json"),
+            &HashMap::new(),
+            &options,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(server.hits("1.12.json"), 0);
+        assert!(asset_paths(&repository, &hierarchy).is_empty());
+        assert!(repository
+            .indices
+            .iter()
+            .any(|index| index.local_path == hierarchy.version_dir.join("client.jar")));
+        let features = HashMap::new();
+        let command = crate::process::GameCommand::from_version_info(
+            &hierarchy,
+            repository.version_info(),
+            &features,
+            "Steve",
+        );
+        assert_eq!(command.main_class, "net.minecraft.client.main.Main");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn failed_download_names_its_index() {
+        let dir = temp_dir();
+        let hierarchy = Hierarchy::builder(&dir, "1.12.2").build();
+        let server = MockServer::start().await;
+        server.set("client.jar", "client");
+        let mut info = version_json(&server.url(""));
+        info["libraries"] = serde_json::json!([{
+            "name": "a:missing:1.0",
+            "downloads": {
+                "artifact": {
+                    "path": "missing.jar",
+                    "sha1": "",
+                    "size": 0,
+                    "url": server.url("missing.jar"),
+                },
+            },
+        }]);
+
+        let repository = fetch(&server, &hierarchy, &info).await;
+        let result = repository.track_all().pull(&Manager::default(), 4).await;
+
+        match result {
+            Err(crate::Error::Download { path, url, .. }) => {
+                assert_eq!(path, hierarchy.libraries_dir.join("missing.jar"));
+                assert_eq!(url, server.url("missing.jar"));
+            }
+            other => panic!("expected a download error, got {:?}", other),
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn undeclared_sizes_are_probed() {
+        let dir = temp_dir();
+        let hierarchy = Hierarchy::builder(&dir, "1.12.2").build();
+        let server = MockServer::start().await;
+        let mut info = version_json(&server.url(""));
+        info["libraries"] = serde_json::json!([{
+            "name": "a:b:1.0",
+            "downloads": {
+                "artifact": {
+                    "path": "a/b/1.0/b-1.0.jar",
+                    "sha1": "",
+                    "size": 3,
+                    "url": server.url("b-1.0.jar"),
+                },
+            },
+        }]);
+        server.set("client.jar", vec![0; 1234]);
+        server.set("b-1.0.jar", "lib");
+        let repository = fetch(&server, &hierarchy, &info).await;
+
+        let tracked = repository.track_all();
+        assert_eq!(tracked.bytes_size(), 3);
+        let downloader = Manager::default();
+        assert_eq!(
+            tracked.probe_bytes_size(&downloader, 4).await.unwrap(),
+            1237
+        );
+        assert_eq!(server.hits("client.jar"), 1);
+        // declared sizes are trusted
+        assert_eq!(server.hits("b-1.0.jar"), 0);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(feature = "sha1")]
+    #[tokio::test]
+    async fn tampered_info_json_is_refetched() {
+        use sha1::{Digest, Sha1};
+
+        let dir = temp_dir();
+        let hierarchy = Hierarchy::builder(&dir, "1.12.2").build();
+        let server = MockServer::start().await;
+        let info = version_json(&server.url("")).to_string();
+        server.set("1.12.2.json", info.as_str());
+        server.set("1.12.json", r#"{"objects":{}}"#);
+        let version = |sha1: String| -> Version {
+            serde_json::from_value(serde_json::json!({
+                "id": "1.12.2",
+                "type": "release",
+                "url": server.url("1.12.2.json"),
+                "time": "2017-09-18T08:39:46Z",
+                "releaseTime": "2017-09-18T08:39:46Z",
+                "sha1": sha1,
+            }))
+            .unwrap()
+        };
+        let info_path = hierarchy.version_dir.join("info.json");
+        std::fs::create_dir_all(&hierarchy.version_dir).unwrap();
+        std::fs::write(&info_path, info.replace("1.12.2", "1.12.1")).unwrap();
+        let downloader = Manager::default();
+
+        let valid = version(format!("{:x}", Sha1::digest(info.as_bytes())));
+        let repository =
+            RemoteRepository::fetch_version(&downloader, &hierarchy, &valid, &HashMap::new())
+                .await
+                .unwrap();
+        assert_eq!(repository.version_info().id, "1.12.2");
+        assert_eq!(std::fs::read_to_string(&info_path).unwrap(), info);
+        assert_eq!(server.hits("1.12.2.json"), 1);
+
+        // a valid cache isn't downloaded again, while a bad remote is reported
+        RemoteRepository::fetch_version(&downloader, &hierarchy, &valid, &HashMap::new())
+            .await
+            .unwrap();
+        assert_eq!(server.hits("1.12.2.json"), 1);
+        let invalid = version(format!("{:x}", Sha1::digest(b"other")));
+        assert!(matches!(
+            RemoteRepository::fetch_version(&downloader, &hierarchy, &invalid, &HashMap::new())
+                .await,
+            Err(crate::Error::ChecksumMismatch(path)) if path == info_path
+        ));
+        assert_eq!(server.hits("1.12.2.json"), 2);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn indices_list_every_managed_file() {
+        let dir = temp_dir();
+        let hierarchy = Hierarchy::builder(&dir, "1.12.2").build();
+        let server = MockServer::start().await;
+        let info = natives_version(&server).await;
+        let asset_index = serde_json::json!({
+            "objects": {
+                "a.ogg": { "hash": "bdf48ef6b5d0d23bbb02e17d04865216179f510a", "size": 4 },
+                "b.ogg": { "hash": "e3a15a6c4e9f9c1d2a3b4c5d6e7f8091a2b3c4d5", "size": 7 },
+            },
+        });
+        let repository = fetch_with_assets(&server, &hierarchy, &info, &asset_index).await;
+
+        // two assets, the native artifact of the current os and the client
+        let indices: Vec<_> = repository.indices().collect();
+        assert_eq!(indices.len(), 4);
+        assert_eq!(indices.iter().map(|index| index.size).sum::<u64>(), 11);
+        let natives: Vec<_> = indices
+            .iter()
+            .filter(|index| index.extract_dir.is_some())
+            .collect();
+        assert_eq!(natives.len(), 1);
+        assert_eq!(natives[0].extract_dir, Some(repository.natives_dir()));
+        assert!(indices.iter().any(|index| {
+            index.local_path == hierarchy.version_dir.join("client.jar")
+                && *index.url == server.url("client.jar")
+        }));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn only_corrupt_assets_are_repaired() {
+        let dir = temp_dir();
+        let hierarchy = Hierarchy::builder(&dir, "1.12.2").build();
+        let server = MockServer::start().await;
+        let info = version_json(&server.url(""));
+        let objects = [
+            ("one", "fe05bcdcdc4928012781a5f1a2a77cbb5398e106"),
+            ("two", "ad782ecdac770fc6eb9a62e44f90873fb97fb26b"),
+            ("three", "b802f384302cb24fbab0a44997e820bf2e8507bb"),
+        ];
+        let asset_index = serde_json::json!({
+            "objects": objects
+                .iter()
+                .map(|(content, hash)| {
+                    (content.to_string(), serde_json::json!({ "hash": hash, "size": content.len() }))
+                })
+                .collect::<serde_json::Map<_, _>>(),
+        });
+        let mut repository = fetch_with_assets(&server, &hierarchy, &info, &asset_index).await;
+        // served by the mock instead of the resources registry
+        for (content, hash) in objects {
+            server.set(hash, content);
+        }
+        for index in &mut repository.indices {
+            if index.category == IndexCategory::Asset {
+                let hash = index.local_path.file_name().unwrap().to_str().unwrap();
+                index.metadata.url = server.url(hash);
+            }
+        }
+        let downloader = Manager::default();
+        assert_eq!(repository.repair_assets(&downloader, 4).await.unwrap(), 3);
+
+        for (_, hash) in &objects[..2] {
+            let path = hierarchy
+                .assets_dir
+                .join("objects")
+                .join(&hash[..2])
+                .join(hash);
+            std::fs::write(path, "corrupt").unwrap();
+        }
+        assert_eq!(repository.repair_assets(&downloader, 4).await.unwrap(), 2);
+        for (content, hash) in objects {
+            let path = hierarchy
+                .assets_dir
+                .join("objects")
+                .join(&hash[..2])
+                .join(hash);
+            assert_eq!(std::fs::read(path).unwrap(), content.as_bytes());
+        }
+        assert_eq!(server.hits(objects[0].1), 2);
+        assert_eq!(server.hits(objects[2].1), 1);
+        // the client isn't part of the repair
+        assert_eq!(server.hits("client.jar"), 0);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn downloaded_bytes_are_split_by_category() {
+        let dir = temp_dir();
+        let hierarchy = Hierarchy::builder(&dir, "1.12.2").build();
+        let server = MockServer::start().await;
+        let mut info = natives_version(&server).await;
+        info["libraries"]
+            .as_array_mut()
+            .unwrap()
+            .push(serde_json::json!({
+                "name": "a:b:1.0",
+                "downloads": {
+                    "artifact": {
+                        "path": "a/b/1.0/b-1.0.jar",
+                        "sha1": "",
+                        "size": 0,
+                        "url": server.url("b-1.0.jar"),
+                    },
+                },
+            }));
+        server.set("b-1.0.jar", "library");
+        let asset_index = r#"{"objects":{}}"#;
+        server.set("1.12.2.json", info.to_string());
+        server.set("1.12.json", asset_index);
+        let downloader = Manager::default();
+        let repository =
+            RemoteRepository::fetch(&downloader, &hierarchy, server.url("1.12.2.json"))
+                .await
+                .unwrap();
+        repository.track_all().pull(&downloader, 4).await.unwrap();
+
+        let native_len = zip(&[("liblwjgl.so", b"native")]).len() as u64;
+        let metadata_len = (info.to_string().len() + asset_index.len()) as u64;
+        assert_eq!(
+            downloader.category_bytes(IndexCategory::Metadata),
+            metadata_len
+        );
+        assert_eq!(downloader.category_bytes(IndexCategory::Library), 7);
+        assert_eq!(downloader.category_bytes(IndexCategory::Native), native_len);
+        assert_eq!(downloader.category_bytes(IndexCategory::Client), 6);
+        assert_eq!(downloader.category_bytes(IndexCategory::Asset), 0);
+        assert_eq!(
+            downloader.downloaded_bytes(),
+            metadata_len + 7 + native_len + 6
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn pull_stops_on_shutdown() {
+        let dir = temp_dir();
+        let hierarchy = Hierarchy::builder(&dir, "1.12.2").build();
+        let server = MockServer::start().await;
+        server.set("client.jar", "client");
+        let repository = fetch(&server, &hierarchy, &version_json(&server.url(""))).await;
+        let downloader = Manager::default();
+        let tracked = repository.track_all();
+
+        let completed = tracked
+            .pull_until(&downloader, 4, future::ready(()))
+            .await
+            .unwrap();
+        assert!(!completed);
+        assert_eq!(downloader.downloaded_bytes(), 0);
+
+        let completed = tracked
+            .pull_until(&downloader, 4, future::pending::<()>())
+            .await
+            .unwrap();
+        assert!(completed);
+        assert!(hierarchy.version_dir.join("client.jar").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn partial_downloads_of_managed_files_are_removed() {
+        let dir = temp_dir();
+        let hierarchy = Hierarchy::builder(&dir, "1.12.2").build();
+        let server = MockServer::start().await;
+        let repository = fetch(&server, &hierarchy, &version_json(&server.url(""))).await;
+        let partial = hierarchy.version_dir.join("client.jar.part");
+        let foreign = hierarchy.version_dir.join("notes.part");
+        std::fs::write(&partial, "cli").unwrap();
+        std::fs::write(&foreign, "mine").unwrap();
+
+        assert_eq!(
+            repository.clean_partial().await.unwrap(),
+            std::slice::from_ref(&partial)
+        );
+        assert!(!partial.exists());
+        assert!(foreign.exists());
+        assert!(repository.clean_partial().await.unwrap().is_empty());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn critical_files_are_pulled_before_assets() {
+        #[derive(Debug, Default)]
+        struct FinishOrder(std::sync::Mutex<Vec<PathBuf>>);
+
+        impl crate::io::download::DownloadObserver for FinishOrder {
+            fn on_file_finished(&self, path: &Path) {
+                self.0.lock().unwrap().push(path.to_path_buf());
+            }
+        }
+
+        let dir = temp_dir();
+        let hierarchy = Hierarchy::builder(&dir, "1.12.2").build();
+        let server = MockServer::start().await;
+        server.set("client.jar", "client");
+        let info = version_json(&server.url(""));
+        let hash = "fe05bcdcdc4928012781a5f1a2a77cbb5398e106";
+        let asset_index = serde_json::json!({ "objects": { "one": { "hash": hash, "size": 3 } } });
+        let mut repository = fetch_with_assets(&server, &hierarchy, &info, &asset_index).await;
+        server.set(hash, "one");
+        for index in &mut repository.indices {
+            if index.category == IndexCategory::Asset {
+                index.metadata.url = server.url(hash);
+            }
+        }
+        // assets are declared first
+        assert_eq!(
+            repository.indices().next().unwrap().category,
+            IndexCategory::Asset
+        );
+
+        let order = std::sync::Arc::new(FinishOrder::default());
+        let downloader = Manager::default().with_observer(order.clone());
+        repository.track_all().pull(&downloader, 1).await.unwrap();
+        assert_eq!(
+            *order.0.lock().unwrap(),
+            [
+                hierarchy.version_dir.join("client.jar"),
+                hierarchy.assets_dir.join("objects/fe").join(hash),
+            ]
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Every file under `dir` with its content, paths relative to `dir`.
+    fn snapshot(dir: &Path) -> Vec<(PathBuf, Vec<u8>)> {
+        let mut files = Vec::new();
+        let mut dirs = vec![dir.to_path_buf()];
+        while let Some(current) = dirs.pop() {
+            for entry in std::fs::read_dir(current).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    dirs.push(path);
+                } else {
+                    let content = std::fs::read(&path).unwrap();
+                    files.push((path.strip_prefix(dir).unwrap().to_path_buf(), content));
+                }
+            }
+        }
+        files.sort();
+        files
+    }
+
+    #[tokio::test]
+    async fn sync_matches_track_invalid_and_pull() {
+        let server = MockServer::start().await;
+        let info = natives_version(&server).await;
+        let downloader = Manager::default();
+        let (fused_dir, phased_dir) = (temp_dir(), temp_dir());
+        for dir in [&fused_dir, &phased_dir] {
+            let hierarchy = Hierarchy::builder(dir, "1.12.2").build();
+            std::fs::create_dir_all(&hierarchy.natives_dir).unwrap();
+            std::fs::write(hierarchy.natives_dir.join("stale.so"), "stale").unwrap();
+        }
+
+        let hierarchy = Hierarchy::builder(&fused_dir, "1.12.2").build();
+        let repository = fetch(&server, &hierarchy, &info).await;
+        assert_eq!(repository.sync(&downloader, 4).await.unwrap(), 2);
+        let hierarchy = Hierarchy::builder(&phased_dir, "1.12.2").build();
+        let repository = fetch(&server, &hierarchy, &info).await;
+        repository
+            .track_invalid()
+            .await
+            .unwrap()
+            .pull(&downloader, 4)
+            .await
+            .unwrap();
+
+        let fused = snapshot(&fused_dir);
+        assert!(!fused.iter().any(|(path, _)| path.ends_with("stale.so")));
+        assert_eq!(fused, snapshot(&phased_dir));
+        std::fs::remove_dir_all(fused_dir).unwrap();
+        std::fs::remove_dir_all(phased_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn overwriting_a_different_native_warns() {
+        let dir = temp_dir();
+        let hierarchy = Hierarchy::builder(&dir, "1.12.2").build();
+        let server = MockServer::start().await;
+        let mut info = natives_version(&server).await;
+        for os in ["linux", "windows", "macos"] {
+            let archive = zip(&[("liblwjgl.so", b"other native")]);
+            server.set(&format!("lwjgl-glfw-natives-{}.jar", os), archive);
+        }
+        info["libraries"]
+            .as_array_mut()
+            .unwrap()
+            .push(natives_library_json(&server.url(""), "lwjgl-glfw"));
+        let repository = fetch(&server, &hierarchy, &info).await;
+        // installs the collector before pulling
+        warnings();
+
+        repository
+            .track_all()
+            .pull(&Manager::default(), 1)
+            .await
+            .unwrap();
+
+        let native = format!("{:?}", hierarchy.natives_dir.join("liblwjgl.so"));
+        let overwrites: Vec<_> = warnings()
+            .into_iter()
+            .filter(|warning| warning.contains(&native))
+            .collect();
+        assert_eq!(overwrites.len(), 1, "{:?}", overwrites);
+        assert!(overwrites[0].contains("Native overwrites a different file"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn overwriting_an_identical_native_is_silent() {
+        let dir = temp_dir();
+        let hierarchy = Hierarchy::builder(&dir, "1.12.2").build();
+        let server = MockServer::start().await;
+        let mut info = natives_version(&server).await;
+        for os in ["linux", "windows", "macos"] {
+            let archive = zip(&[("liblwjgl.so", b"native")]);
+            server.set(&format!("lwjgl-glfw-natives-{}.jar", os), archive);
+        }
+        info["libraries"]
+            .as_array_mut()
+            .unwrap()
+            .push(natives_library_json(&server.url(""), "lwjgl-glfw"));
+        let repository = fetch(&server, &hierarchy, &info).await;
+        warnings();
+
+        repository
+            .track_all()
+            .pull(&Manager::default(), 1)
+            .await
+            .unwrap();
+
+        let native = format!("{:?}", hierarchy.natives_dir.join("liblwjgl.so"));
+        assert!(!warnings().iter().any(|warning| warning.contains(&native)));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn wiped_natives_are_restored_from_the_cached_artifact() {
+        let dir = temp_dir();
+        let hierarchy = Hierarchy::builder(&dir, "1.12.2").build();
+        let server = MockServer::start().await;
+        let mut repository = fetch(&server, &hierarchy, &natives_version(&server).await).await;
+        // the fixture doesn't declare sizes, so make the kept artifact valid
+        let archive_len = zip(&[("liblwjgl.so", b"native")]).len() as u64;
+        for index in repository
+            .indices
+            .iter_mut()
+            .filter(|index| index.extract_dir().is_some())
+        {
+            index.metadata.size = archive_len;
+            index.metadata.checksum = Checksum::None;
+        }
+        let downloader = Manager::default();
+        repository.track_all().pull(&downloader, 4).await.unwrap();
+        let hits = |server: &MockServer| {
+            ["linux", "windows", "macos"]
+                .iter()
+                .map(|os| server.hits(&format!("lwjgl-natives-{}.jar", os)))
+                .sum::<usize>()
+        };
+        let downloads = hits(&server);
+        std::fs::remove_dir_all(&hierarchy.natives_dir).unwrap();
+
+        repository
+            .track_invalid()
+            .await
+            .unwrap()
+            .pull(&downloader, 4)
+            .await
+            .unwrap();
+
+        assert!(hierarchy.natives_dir.join("liblwjgl.so").exists());
+        assert_eq!(hits(&server), downloads);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Client jar of the right size but with different content, and whether it's tracked.
+    async fn corrupt_client_is_tracked() -> bool {
+        let dir = temp_dir();
+        let hierarchy = Hierarchy::builder(&dir, "1.12.2").build();
+        let server = MockServer::start().await;
+        let mut info = version_json(&server.url(""));
+        info["downloads"]["client"]["sha1"] = "d2a04d71301a8915217dd5faf81d12cffd6cd958".into();
+        info["downloads"]["client"]["size"] = 6.into();
+        let repository = fetch(&server, &hierarchy, &info).await;
+        fs::write(hierarchy.version_dir.join("client.jar"), "CLIENT")
+            .await
+            .unwrap();
+
+        let tracked = repository.track_invalid().await.unwrap();
+        let client_tracked = tracked
+            .tracked
+            .iter()
+            .any(|index| index.category == IndexCategory::Client);
+        std::fs::remove_dir_all(dir).unwrap();
+        client_tracked
+    }
+
+    #[cfg(feature = "sha1")]
+    #[tokio::test]
+    async fn checksum_level_catches_corruption_of_the_same_size() {
+        assert_eq!(ValidationLevel::current(), ValidationLevel::Checksum);
+        assert!(corrupt_client_is_tracked().await);
+    }
+
+    #[cfg(not(feature = "sha1"))]
+    #[tokio::test]
+    async fn size_level_misses_corruption_of_the_same_size() {
+        assert_eq!(ValidationLevel::current(), ValidationLevel::Size);
+        assert!(!corrupt_client_is_tracked().await);
+    }
+
+    #[tokio::test]
+    async fn natives_only_library_is_pulled_but_not_on_classpath() {
+        let dir = temp_dir();
+        let hierarchy = Hierarchy::builder(&dir, "1.12.2").build();
+        let server = MockServer::start().await;
+        let repository = fetch(&server, &hierarchy, &natives_version(&server).await).await;
+
+        repository
+            .track_all()
+            .pull(&Manager::default(), 4)
+            .await
+            .unwrap();
+
+        let info = repository.version_info();
+        let library = &info.libraries[0];
+        assert!(library.resources.artifact.is_none());
+        let native = library.get_native_for(&Target::current()).unwrap();
+        assert!(hierarchy.libraries_dir.join(&native.path).exists());
+        assert!(hierarchy.natives_dir.join("liblwjgl.so").exists());
+        assert_eq!(
+            info.resolved_classpath(&hierarchy, &HashMap::new()),
+            [hierarchy.version_dir.join("client.jar")]
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn native_extraction_is_traced() {
+        let dir = temp_dir();
+        let hierarchy = Hierarchy::builder(&dir, "1.12.2").build();
+        let server = MockServer::start().await;
+        let repository = fetch(&server, &hierarchy, &natives_version(&server).await).await;
+        spans("extract_natives");
+
+        repository
+            .track_all()
+            .pull(&Manager::default(), 4)
+            .await
+            .unwrap();
+
+        let extract_dir = format!("extract_dir={:?}", hierarchy.natives_dir);
+        let extractions: Vec<_> = spans("extract_natives")
+            .into_iter()
+            .filter(|fields| fields.contains(&extract_dir))
+            .collect();
+        assert_eq!(extractions.len(), 1, "{:?}", extractions);
+        assert!(extractions[0].contains("archive_path="));
+        assert!(extractions[0].ends_with("entries=1"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn traversing_entry_is_refused() {
+        let dir = temp_dir();
+        let archive_path = dir.join("natives.jar");
+        std::fs::write(
+            &archive_path,
+            zip(&[("lib.so", b"native"), ("../evil", b"evil")]),
+        )
+        .unwrap();
+        let extract_dir = dir.join("natives");
+        let index = Index {
+            category: IndexCategory::Native,
+            metadata: RemoteMetadata {
+                url: Url::parse("https://example.invalid/natives.jar").unwrap(),
+                size: 0,
+                checksum: Checksum::None,
+            },
+            local_path: archive_path,
+            itype: IndexType::NativeArtifact {
+                extract_dir: extract_dir.clone(),
+                cache_dir: None,
+            },
+        };
+
+        let result = index.extract(&BlockingPool::default()).await;
+        assert!(matches!(
+            result,
+            Err(crate::Error::UnsafeArchiveEntry { entry, .. }) if entry == "../evil"
+        ));
+        assert!(!dir.join("evil").exists());
+        // nothing is extracted, not even the safe entries
+        assert!(!extract_dir.exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn pre_1_6_assets_are_stored_in_resources() {
+        let dir = temp_dir();
+        let hierarchy = Hierarchy::builder(&dir, "1.5.2").build();
+        let server = MockServer::start().await;
+        let mut info = version_json(&server.url(""));
+        info["assets"] = "pre-1.6".into();
+        info["minecraftArguments"] =
+            "--username ${auth_player_name} ${game_dir} ${game_assets}".into();
+        let asset_index = serde_json::json!({
+            "map_to_resources": true,
+            "objects": {
+                "sound/a.ogg": { "hash": "bdf48ef6b5d0d23bbb02e17d04865216179f510a", "size": 4 },
+            },
+        });
+
+        let repository = fetch_with_assets(&server, &hierarchy, &info, &asset_index).await;
+        let assets: Vec<_> = repository
+            .indices
+            .iter()
+            .filter(|index| index.category == IndexCategory::Asset)
+            .map(|index| index.local_path.as_path())
+            .collect();
+        assert_eq!(assets, [hierarchy.resources_dir().join("sound/a.ogg")]);
+
+        let features = HashMap::new();
+        let command = crate::process::GameCommand::from_version_info(
+            &hierarchy,
+            repository.version_info(),
+            &features,
+            "Steve",
+        );
+        let args: Vec<_> = command
+            .build("java")
+            .get_args()
+            .map(PathBuf::from)
+            .collect();
+        assert_eq!(args.last(), Some(&hierarchy.resources_dir()));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn traversing_legacy_asset_name_is_refused() {
+        let server = MockServer::start().await;
+        let info = version_json(&server.url(""));
+        server.set("1.12.2.json", info.to_string());
+        for legacy in ["map_to_resources", "virtual"] {
+            let dir = temp_dir();
+            let hierarchy = Hierarchy::builder(&dir, "1.12.2").build();
+            let asset_index = serde_json::json!({
+                legacy: true,
+                "objects": {
+                    "../../../evil.ogg": {
+                        "hash": "bdf48ef6b5d0d23bbb02e17d04865216179f510a",
+                        "size": 4,
+                    },
+                },
+            });
+            server.set("1.12.json", asset_index.to_string());
+
+            let result =
+                RemoteRepository::fetch(&Manager::default(), &hierarchy, server.url("1.12.2.json"))
+                    .await;
+            assert!(result.is_err(), "{}", legacy);
+            std::fs::remove_dir_all(dir).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn ensure_installed_twice_downloads_nothing_more() {
+        let dir = temp_dir();
+        let hierarchy = Hierarchy::builder(&dir, "1.12.2").build();
+        let server = MockServer::start().await;
+        // valid sizes and checksums, so installed files are recognized
+        let mut info = version_json(&server.url(""));
+        info["downloads"]["client"]["sha1"] = "d2a04d71301a8915217dd5faf81d12cffd6cd958".into();
+        info["downloads"]["client"]["size"] = 6.into();
+        info["assetIndex"]["sha1"] = "62ea787c1f800c091b98678b050453a5ae59d7bc".into();
+        info["assetIndex"]["size"] = 14.into();
+        server.set("1.12.2.json", info.to_string());
+        server.set("1.12.json", r#"{"objects":{}}"#);
+        server.set("client.jar", "client");
+        let version: Version = serde_json::from_value(serde_json::json!({
+            "id": "1.12.2",
+            "type": "release",
+            "url": server.url("1.12.2.json"),
+            "time": "2017-09-18T08:39:46+00:00",
+            "releaseTime": "2017-09-18T08:39:46+00:00",
+        }))
+        .unwrap();
+        let downloader = Manager::default();
+        let files = ["1.12.2.json", "1.12.json", "client.jar"];
+
+        let installed = ensure_installed(&downloader, &hierarchy, &version, 4)
+            .await
+            .unwrap();
+        assert_eq!(installed.id, "1.12.2");
+        let hits: Vec<_> = files.iter().map(|file| server.hits(file)).collect();
+        assert_eq!(hits, [1, 1, 1]);
+
+        ensure_installed(&downloader, &hierarchy, &version, 4)
+            .await
+            .unwrap();
+        let hits_again: Vec<_> = files.iter().map(|file| server.hits(file)).collect();
+        assert_eq!(hits_again, hits);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn continue_on_error_pulls_the_other_files() {
+        let dir = temp_dir();
+        let hierarchy = Hierarchy::builder(&dir, "1.12.2").build();
+        let server = MockServer::start().await;
+        let mut info = version_json(&server.url(""));
+        let libraries: Vec<_> = ["a", "b", "c", "d"]
+            .iter()
+            .map(|name| {
+                let path = format!("{}.jar", name);
+                serde_json::json!({
+                    "name": format!("test:{}:1.0", name),
+                    "downloads": {
+                        "artifact": { "path": path, "sha1": "", "size": 0, "url": server.url(&path) },
+                    },
+                })
+            })
+            .collect();
+        info["libraries"] = Value::Array(libraries);
+        server.set("client.jar", "client");
+        server.set("a.jar", "a");
+        server.set("c.jar", "c");
+        let repository = fetch(&server, &hierarchy, &info).await;
+        let tracked = repository.track_all();
+        let total = tracked.indices().count();
+
+        let (report, failures) = tracked
+            .pull_continue_on_error(&Manager::default(), 4)
+            .await
+            .unwrap();
+
+        let mut failed: Vec<_> = failures
+            .iter()
+            .map(|failure| failure.path.strip_prefix(&hierarchy.libraries_dir).unwrap())
+            .collect();
+        failed.sort();
+        assert_eq!(failed, [Path::new("b.jar"), Path::new("d.jar")]);
+        assert_eq!(report.failed, 2);
+        assert_eq!(report.downloaded, total - 2);
+        for file in ["a.jar", "c.jar"] {
+            assert!(hierarchy.libraries_dir.join(file).exists());
+        }
+        assert!(hierarchy.version_dir.join("client.jar").exists());
+        // fail-fast stays the default
+        assert!(repository
+            .track_all()
+            .pull(&Manager::default(), 4)
+            .await
+            .is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(feature = "sha1")]
+    #[tokio::test]
+    async fn recorded_files_are_not_hashed_again() {
+        let dir = temp_dir();
+        let hierarchy = Hierarchy::builder(&dir, "1.12.2").build();
+        let server = MockServer::start().await;
+        let mut info = version_json(&server.url(""));
+        info["downloads"]["client"]["sha1"] = "d2a04d71301a8915217dd5faf81d12cffd6cd958".into();
+        info["downloads"]["client"]["size"] = 6.into();
+        let mut repository = fetch(&server, &hierarchy, &info).await;
+        let client_path = hierarchy.version_dir.join("client.jar");
+        fs::write(&client_path, "client").await.unwrap();
+        let client_hashes = || {
+            let path = format!("path={:?}", client_path);
+            spans("sha1_digest")
+                .iter()
+                .filter(|fields| fields.contains(&path))
+                .count()
+        };
+        let mut state = ValidationState::default();
+
+        repository
+            .track_invalid_with_state(&mut state)
+            .await
+            .unwrap();
+        assert_eq!(client_hashes(), 1);
+        let tracked = repository
+            .track_invalid_with_state(&mut state)
+            .await
+            .unwrap();
+        assert_eq!(client_hashes(), 1);
+        assert!(!tracked
+            .indices()
+            .any(|index| index.category == IndexCategory::Client));
+
+        // a new remote checksum invalidates the entry
+        let client = repository
+            .indices
+            .iter_mut()
+            .find(|index| index.category == IndexCategory::Client)
+            .unwrap();
+        client.metadata.checksum = Checksum::Sha1("0".repeat(40));
+        let tracked = repository
+            .track_invalid_with_state(&mut state)
+            .await
+            .unwrap();
+        assert_eq!(client_hashes(), 2);
+        assert!(tracked
+            .indices()
+            .any(|index| index.category == IndexCategory::Client));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn malformed_asset_index_names_its_id() {
+        let dir = temp_dir();
+        let hierarchy = Hierarchy::builder(&dir, "1.12.2").build();
+        let server = MockServer::start().await;
+        server.set("1.12.2.json", version_json(&server.url("")).to_string());
+        server.set("1.12.json", "{\"objects\": [");
+
+        let err =
+            RemoteRepository::fetch(&Manager::default(), &hierarchy, server.url("1.12.2.json"))
+                .await
+                .err()
+                .expect("malformed asset index was accepted");
+
+        match &err {
+            crate::Error::AssetIndexParse { id, path, .. } => {
+                assert_eq!(id, "1.12");
+                assert_eq!(*path, hierarchy.assets_dir.join("indexes/1.12.json"));
+            }
+            other => panic!("expected an asset index parse error, got {:?}", other),
+        }
+        assert!(err
+            .to_string()
+            .starts_with("failed to parse asset index 1.12 at "));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn natives_lists_allowed_native_artifacts() {
+        let dir = temp_dir();
+        let hierarchy = Hierarchy::builder(&dir, "1.12.2").build();
+        let server = MockServer::start().await;
+        let mut info = version_json(&server.url(""));
+        let mut disallowed = natives_library_json(&server.url(""), "jinput");
+        disallowed["rules"] = serde_json::json!([{ "action": "disallow" }]);
+        info["libraries"] = serde_json::json!([
+            natives_library_json(&server.url(""), "lwjgl"),
+            disallowed,
+            {
+                "name": "a:plain:1.0",
+                "downloads": {
+                    "artifact": {
+                        "path": "plain.jar",
+                        "sha1": "",
+                        "size": 0,
+                        "url": server.url("plain.jar"),
+                    },
+                },
+            },
+        ]);
+
+        let repository = fetch(&server, &hierarchy, &info).await;
+        let natives: Vec<_> = repository.natives().collect();
+
+        assert_eq!(natives.len(), 1);
+        assert!(natives[0].url.as_str().contains("lwjgl-natives-"));
+        assert_eq!(
+            natives[0].extract_dir,
+            Some(hierarchy.natives_dir.as_path())
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn assets_outside_prefixes_are_not_tracked() {
+        let dir = temp_dir();
+        let hierarchy = Hierarchy::builder(&dir, "1.12.2").build();
+        let server = MockServer::start().await;
+        server.set("1.12.2.json", version_json(&server.url("")).to_string());
+        let (lang, sound) = (
+            "bdf48ef6b5d0d23bbb02e17d04865216179f510a",
+            "62ea787c1f800c091b98678b050453a5ae59d7bc",
+        );
+        let objects = serde_json::json!({
+            "minecraft/lang/de_de.json": { "hash": lang, "size": 4 },
+            "minecraft/sounds/a.ogg": { "hash": sound, "size": 4 },
+        });
+        let options = FetchOptions {
+            asset_prefixes: Some(vec!["minecraft/lang/".to_owned()]),
+            ..Default::default()
+        };
+        let (downloader, features) = (Manager::default(), HashMap::new());
+        let fetch = || {
+            RemoteRepository::fetch_with_options(
+                &downloader,
+                &hierarchy,
+                server.url("1.12.2.json"),
+                &features,
+                &options,
+            )
+        };
+
+        server.set(
+            "1.12.json",
+            serde_json::json!({ "objects": objects }).to_string(),
+        );
+        let repository = fetch().await.unwrap();
+        assert_eq!(
+            asset_paths(&repository, &hierarchy),
+            [Path::new("objects/bd").join(lang)]
+        );
+
+        // legacy layout, see objects_sharing_a_hash_are_pulled_once
+        std::fs::remove_dir_all(hierarchy.assets_dir.join("indexes")).unwrap();
+        server.set(
+            "1.12.json",
+            serde_json::json!({ "virtual": true, "objects": objects }).to_string(),
+        );
+        let repository = fetch().await.unwrap();
+        assert_eq!(
+            asset_paths(&repository, &hierarchy),
+            [Path::new("virtual/legacy/minecraft/lang/de_de.json")]
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Version whose natives archive `archive` is declared with its size and checksum.
+    async fn checked_natives_version(server: &MockServer, archive: &[u8], dir: &Path) -> Value {
+        let mut info = natives_version(server).await;
+        let archive_path = dir.join("natives.jar");
+        std::fs::write(&archive_path, archive).unwrap();
+        #[cfg(feature = "sha1")]
+        let sha1 = crate::io::hash::sha1_digest(archive_path).await.unwrap();
+        // only the size is verified without the feature
+        #[cfg(not(feature = "sha1"))]
+        let sha1 = "0".repeat(40);
+        for (_, classifier) in info["libraries"][0]["downloads"]["classifiers"]
+            .as_object_mut()
+            .unwrap()
+        {
+            server.set(classifier["path"].as_str().unwrap(), archive);
+            classifier["sha1"] = sha1.clone().into();
+            classifier["size"] = archive.len().into();
+        }
+        info
+    }
+
+    #[tokio::test]
+    async fn versions_sharing_natives_reuse_the_cache() {
+        let dir = temp_dir();
+        let server = MockServer::start().await;
+        let info =
+            checked_natives_version(&server, &zip(&[("liblwjgl.so", b"native")]), &dir).await;
+        let options = FetchOptions {
+            skip_assets: true,
+            natives_cache: Some(dir.join("natives-cache")),
+            ..Default::default()
+        };
+        server.set("1.12.2.json", info.to_string());
+
+        for instance in ["a", "b"] {
+            let hierarchy = Hierarchy::builder(dir.join(instance), "1.12.2").build();
+            let repository = RemoteRepository::fetch_with_options(
+                &Manager::default(),
+                &hierarchy,
+                server.url("1.12.2.json"),
+                &HashMap::new(),
+                &options,
+            )
+            .await
+            .unwrap();
+            repository
+                .track_all()
+                .pull(&Manager::default(), 4)
+                .await
+                .unwrap();
+            let native = hierarchy.natives_dir.join("liblwjgl.so");
+            assert_eq!(std::fs::read(&native).unwrap(), b"native");
+            #[cfg(unix)]
+            assert!(std::fs::symlink_metadata(&native)
+                .unwrap()
+                .file_type()
+                .is_symlink());
+        }
+
+        let entries: Vec<_> = std::fs::read_dir(dir.join("natives-cache"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].len(), 40);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn corrupt_natives_are_not_cached() {
+        let dir = temp_dir();
+        let hierarchy = Hierarchy::builder(&dir, "1.12.2").build();
+        let server = MockServer::start().await;
+        let archive = zip(&[("liblwjgl.so", b"native")]);
+        let info = checked_natives_version(&server, &archive, &dir).await;
+        // served truncated, as by a broken mirror
+        for os in ["linux", "windows", "macos"] {
+            server.set(
+                &format!("lwjgl-natives-{}.jar", os),
+                &archive[..archive.len() - 1],
+            );
+        }
+        let options = FetchOptions {
+            skip_assets: true,
+            natives_cache: Some(dir.join("natives-cache")),
+            ..Default::default()
+        };
+        server.set("1.12.2.json", info.to_string());
+        let repository = RemoteRepository::fetch_with_options(
+            &Manager::default(),
+            &hierarchy,
+            server.url("1.12.2.json"),
+            &HashMap::new(),
+            &options,
+        )
+        .await
+        .unwrap();
+
+        let result = repository.track_all().pull(&Manager::default(), 4).await;
+
+        assert!(matches!(
+            result,
+            Err(crate::Error::ChecksumMismatch(path)) if path.starts_with(&hierarchy.libraries_dir)
+        ));
+        let cached = std::fs::read_dir(dir.join("natives-cache"))
+            .map(|entries| entries.count())
+            .unwrap_or(0);
+        assert_eq!(cached, 0);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn mismatched_asset_index_fails_strict_fetch() {
+        let dir = temp_dir();
+        let hierarchy = Hierarchy::builder(&dir, "1.12.2").build();
+        let server = MockServer::start().await;
+        let mut info = version_json(&server.url(""));
+        info["assetIndex"]["id"] = "1.13".into();
+        server.set("1.12.2.json", info.to_string());
+        server.set("1.12.json", r#"{"objects": {}}"#);
+        let fetch = |strict_asset_index| {
+            let hierarchy = &hierarchy;
+            let url = server.url("1.12.2.json");
+            async move {
+                let options = FetchOptions {
+                    strict_asset_index,
+                    ..Default::default()
+                };
+                RemoteRepository::fetch_with_options(
+                    &Manager::default(),
+                    hierarchy,
+                    url,
+                    &HashMap::new(),
+                    &options,
+                )
+                .await
+            }
+        };
+
+        assert!(matches!(
+            fetch(true).await,
+            Err(crate::Error::AssetIndexMismatch { .. })
+        ));
+        // only warned about otherwise
+        assert!(fetch(false).await.is_ok());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn tracked_files_are_pulled_externally() {
+        let dir = temp_dir();
+        let hierarchy = Hierarchy::builder(&dir, "1.12.2").build();
+        let server = MockServer::start().await;
+        let mut info = version_json(&server.url(""));
+        info["downloads"]["client"]["sha1"] = "d2a04d71301a8915217dd5faf81d12cffd6cd958".into();
+        info["downloads"]["client"]["size"] = 6.into();
+        let repository = fetch(&server, &hierarchy, &info).await;
+        let tracked = repository.track_invalid().await.unwrap();
+        let client_jar = hierarchy.version_dir.join("client.jar");
+
+        let files: Vec<_> = tracked.iter().collect();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].category, IndexCategory::Client);
+        assert_eq!(*files[0].url, server.url("client.jar"));
+        assert_eq!(files[0].local_path, client_jar);
+        assert_eq!(files[0].size, 6);
+        assert_eq!(
+            files[0].sha1,
+            Some("d2a04d71301a8915217dd5faf81d12cffd6cd958")
+        );
+
+        let missing = tracked.complete_external(4).await.unwrap();
+        assert_eq!(missing.len(), 1);
+        assert_eq!(missing[0].local_path, client_jar);
+
+        // as downloaded by another engine
+        fs::write(&client_jar, "client").await.unwrap();
+        assert!(tracked.complete_external(4).await.unwrap().is_empty());
+        assert_eq!(server.hits("client.jar"), 0);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn validation_runs_on_custom_pool() {
+        let dir = temp_dir();
+        let hierarchy = Hierarchy::builder(&dir, "1.12.2").build();
+        let server = MockServer::start().await;
+        let mut info = natives_version(&server).await;
+        info["downloads"]["client"]["sha1"] = "d2a04d71301a8915217dd5faf81d12cffd6cd958".into();
+        info["downloads"]["client"]["size"] = 6.into();
+        server.set("1.12.2.json", info.to_string());
+        server.set("1.12.json", r#"{"objects": {}}"#);
+        let jobs = Arc::new(AtomicUsize::new(0));
+        let pool_jobs = jobs.clone();
+        let options = FetchOptions {
+            blocking_pool: BlockingPool::new(move |job| {
+                pool_jobs.fetch_add(1, Ordering::SeqCst);
+                std::thread::spawn(job);
+            }),
+            ..Default::default()
+        };
+        let repository = RemoteRepository::fetch_with_options(
+            &Manager::default(),
+            &hierarchy,
+            server.url("1.12.2.json"),
+            &HashMap::new(),
+            &options,
+        )
+        .await
+        .unwrap();
+
+        repository
+            .track_invalid()
+            .await
+            .unwrap()
+            .pull(&Manager::default(), 4)
+            .await
+            .unwrap();
+        assert_eq!(repository.track_invalid().await.unwrap().iter().count(), 0);
+        assert_eq!(
+            std::fs::read(hierarchy.natives_dir.join("liblwjgl.so")).unwrap(),
+            b"native"
+        );
+        // natives extraction, and hashing with the feature
+        assert!(jobs.load(Ordering::SeqCst) > 0);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn natives_are_extracted_again_offline() {
+        let dir = temp_dir();
+        let hierarchy = Hierarchy::builder(&dir, "1.12.2").build();
+        let server = MockServer::start().await;
+        let repository = fetch(&server, &hierarchy, &natives_version(&server).await).await;
+        repository
+            .track_all()
+            .pull(&Manager::default(), 4)
+            .await
+            .unwrap();
+        let requests = |server: &MockServer| {
+            ["linux", "windows", "macos"]
+                .iter()
+                .map(|os| server.hits(&format!("lwjgl-natives-{}.jar", os)))
+                .sum::<usize>()
+        };
+        let pulled = requests(&server);
+
+        std::fs::remove_dir_all(&hierarchy.natives_dir).unwrap();
+        std::fs::create_dir_all(&hierarchy.natives_dir).unwrap();
+        std::fs::write(hierarchy.natives_dir.join("stale.so"), b"stale").unwrap();
+        repository.extract_natives().await.unwrap();
+
+        assert_eq!(
+            std::fs::read(hierarchy.natives_dir.join("liblwjgl.so")).unwrap(),
+            b"native"
+        );
+        assert!(!hierarchy.natives_dir.join("stale.so").exists());
+        assert_eq!(requests(&server), pulled);
+
+        // nothing to extract from, the natives dir is kept as is
+        std::fs::remove_dir_all(&hierarchy.libraries_dir).unwrap();
+        assert!(repository.extract_natives().await.is_err());
+        assert!(hierarchy.natives_dir.join("liblwjgl.so").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn refresh_metadata_downloads_saved_info_again() {
+        let dir = temp_dir();
+        let hierarchy = Hierarchy::builder(&dir, "1.12.2").build();
+        let server = MockServer::start().await;
+        let mut info = version_json(&server.url(""));
+        fetch(&server, &hierarchy, &info).await;
+        // re-published meanwhile
+        info["mainClass"] = "net.minecraft.client.main.Republished".into();
+        server.set("1.12.2.json", info.to_string());
+        let fetch_with = |refresh_metadata| {
+            let (hierarchy, url) = (&hierarchy, server.url("1.12.2.json"));
+            async move {
+                let options = FetchOptions {
+                    refresh_metadata,
+                    ..Default::default()
+                };
+                RemoteRepository::fetch_with_options(
+                    &Manager::default(),
+                    hierarchy,
+                    url,
+                    &HashMap::new(),
+                    &options,
+                )
+                .await
+                .unwrap()
+            }
+        };
+
+        let kept = fetch_with(false).await;
+        assert_eq!(
+            kept.version_info().main_class,
+            "net.minecraft.client.main.Main"
+        );
+        assert_eq!(server.hits("1.12.2.json"), 1);
+
+        let refreshed = fetch_with(true).await;
+        assert_eq!(
+            refreshed.version_info().main_class,
+            "net.minecraft.client.main.Republished"
+        );
+        assert_eq!(server.hits("1.12.2.json"), 2);
+        std::fs::remove_dir_all(dir).unwrap();
     }
 }
@@ -1,9 +1,20 @@
-use std::result;
+use std::{path::PathBuf, result};
 
+use url::Url;
+
+#[cfg(feature = "blocking")]
+pub mod blocking;
+pub mod interop;
 pub mod io;
+pub mod loaders;
+#[cfg(feature = "maintenance")]
+pub mod maintenance;
 pub mod metadata;
 pub mod process;
+pub mod profiles;
 pub mod resources;
+#[cfg(test)]
+mod testing;
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -15,6 +26,57 @@ pub enum Error {
     TokioJoinError(#[from] tokio::task::JoinError),
     #[error(transparent)]
     ZipError(#[from] zip::result::ZipError),
+    #[error("invalid classpath: {0}")]
+    InvalidClasspath(#[from] std::env::JoinPathsError),
+    #[error("classpath entry {0} contains the path separator")]
+    SeparatorInClasspath(PathBuf),
+    #[error("java {0} doesn't read argument files, 9 or newer is required")]
+    ArgFileUnsupported(usize),
+    #[error(transparent)]
+    UrlParse(#[from] url::ParseError),
+    #[error("failed to download {url} to {}", path.display())]
+    Download {
+        path: PathBuf,
+        url: Url,
+        #[source]
+        source: Box<Error>,
+    },
+    #[error("version {0} isn't listed in the manifest")]
+    UnknownVersion(String),
+    #[error("neither home nor data dirs found")]
+    NoDefaultDirectory,
+    #[error("main class {0} isn't found on the classpath")]
+    MainClassNotFound(String),
+    #[error("{0} doesn't match its checksum")]
+    ChecksumMismatch(PathBuf),
+    #[error("server EULA must be accepted before launching")]
+    EulaNotAccepted,
+    #[error("blocking functions can't be called from within an async runtime")]
+    BlockingInRuntime,
+    #[error("missing local artifact {0}")]
+    MissingLocalArtifact(PathBuf),
+    #[error("forge processor {jar} exited with {status}")]
+    ProcessorFailed {
+        jar: String,
+        status: std::process::ExitStatus,
+    },
+    #[error("entry {entry} of {} escapes the extraction dir", archive.display())]
+    UnsafeArchiveEntry { archive: PathBuf, entry: String },
+    #[error("failed to parse asset index {id} at {}", path.display())]
+    AssetIndexParse {
+        id: String,
+        path: PathBuf,
+        #[source]
+        source: serde_json::Error,
+    },
+    #[error("asset index declares {expected} bytes, but objects sum up to {actual}")]
+    CorruptAssetIndex { expected: u64, actual: u64 },
+    #[error("version uses assets {assets}, but its asset index is {asset_index}")]
+    AssetIndexMismatch { assets: String, asset_index: String },
+    #[error(
+        "version {id} has neither `arguments` nor `minecraftArguments`, its format isn't supported"
+    )]
+    UnsupportedVersionFormat { id: String },
 }
 
 pub type Result<T> = result::Result<T, Error>;
@@ -0,0 +1,156 @@
+use reqwest::Client;
+
+use super::{LoaderProfile, LoaderVersion};
+
+pub static META_URL: &str = "https://meta.fabricmc.net/v2";
+
+/// Fabric loader versions supporting `game_version`, newest first. The loader is the same for
+/// every game version, the game specific part being the intermediary mappings of the profile.
+pub async fn fetch_loader_versions(
+    client: &Client,
+    game_version: &str,
+) -> crate::Result<Vec<LoaderVersion>> {
+    super::fetch_loader_versions(client, META_URL, game_version).await
+}
+
+/// Launcher profile of a Fabric loader version, its libraries are hosted on the Fabric maven.
+pub async fn fetch_profile(
+    client: &Client,
+    game_version: &str,
+    loader_version: &str,
+) -> crate::Result<LoaderProfile> {
+    super::fetch_profile(client, META_URL, game_version, loader_version).await
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use url::Url;
+
+    use super::*;
+    use crate::{
+        metadata::game::VersionInfo,
+        testing::{version_json, MockServer},
+    };
+
+    /// Trimmed down profile of Fabric loader 0.15.3 for 1.20.4.
+    fn profile_json() -> serde_json::Value {
+        json!({
+            "id": "fabric-loader-0.15.3-1.20.4",
+            "inheritsFrom": "1.20.4",
+            "releaseTime": "2023-12-22T18:13:04+0000",
+            "time": "2023-12-22T18:13:04+0000",
+            "type": "release",
+            "mainClass": "net.fabricmc.loader.impl.launch.knot.KnotClient",
+            "arguments": {
+                "game": [],
+                "jvm": ["-DFabricMcEmu= net.minecraft.client.main.Main "],
+            },
+            "libraries": [
+                {
+                    "name": "org.ow2.asm:asm:9.6",
+                    "url": "https://maven.fabricmc.net/",
+                    "sha1": "aa205cf0a06dbd8e04ece91c0b37c3f5d567546a",
+                    "size": 123598,
+                },
+                {
+                    "name": "net.fabricmc:intermediary:1.20.4",
+                    "url": "https://maven.fabricmc.net/",
+                },
+                {
+                    "name": "net.fabricmc:fabric-loader:0.15.3",
+                    "url": "https://maven.fabricmc.net/",
+                },
+            ],
+        })
+    }
+
+    fn vanilla(id: &str) -> VersionInfo {
+        let mut info = version_json(&Url::parse("https://example.invalid/").unwrap());
+        let info_object = info.as_object_mut().unwrap();
+        info_object.remove("minecraftArguments");
+        info_object.insert("id".to_owned(), json!(id));
+        info_object.insert(
+            "arguments".to_owned(),
+            json!({
+                "game": ["--username", "${auth_player_name}"],
+                "jvm": ["-cp", "${classpath}"],
+            }),
+        );
+        let library = |name: &str, path: &str| {
+            let url = format!("https://libraries.minecraft.net/{}", path);
+            json!({
+                "name": name,
+                "downloads": { "artifact": { "path": path, "sha1": "", "size": 0, "url": url } },
+            })
+        };
+        info_object.insert(
+            "libraries".to_owned(),
+            json!([
+                library("org.ow2.asm:asm:9.3", "asm-9.3.jar"),
+                library("com.mojang:brigadier:1.2.9", "brigadier-1.2.9.jar"),
+            ]),
+        );
+        serde_json::from_value(info).unwrap()
+    }
+
+    #[tokio::test]
+    async fn profile_is_fetched_and_merged_over_vanilla() {
+        let server = MockServer::start().await;
+        server.set(
+            "v2/versions/loader/1.20.4/0.15.3/profile/json",
+            profile_json().to_string(),
+        );
+        let meta_url = server.url("v2");
+        let profile =
+            super::super::fetch_profile(&Client::new(), meta_url.as_str(), "1.20.4", "0.15.3")
+                .await
+                .unwrap();
+
+        let merged = profile.merge_into(vanilla("1.20.4")).unwrap();
+        assert_eq!(merged.id, "fabric-loader-0.15.3-1.20.4");
+        assert_eq!(
+            merged.main_class,
+            "net.fabricmc.loader.impl.launch.knot.KnotClient"
+        );
+        let names: Vec<_> = merged
+            .libraries
+            .iter()
+            .map(|lib| lib.name.as_str())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "org.ow2.asm:asm:9.6",
+                "net.fabricmc:intermediary:1.20.4",
+                "net.fabricmc:fabric-loader:0.15.3",
+                "com.mojang:brigadier:1.2.9",
+            ]
+        );
+        let intermediary = merged.libraries[1].resources.artifact.as_ref().unwrap();
+        assert_eq!(
+            intermediary.path,
+            "net/fabricmc/intermediary/1.20.4/intermediary-1.20.4.jar"
+        );
+        assert_eq!(
+            intermediary.resource.url.as_str(),
+            "https://maven.fabricmc.net/net/fabricmc/intermediary/1.20.4/intermediary-1.20.4.jar"
+        );
+        let features = Default::default();
+        let jvm: Vec<_> = merged.arguments.iter_jvm_args(&features).collect();
+        assert_eq!(
+            jvm,
+            [
+                "-cp",
+                "${classpath}",
+                "-DFabricMcEmu= net.minecraft.client.main.Main "
+            ]
+        );
+    }
+
+    #[test]
+    fn profile_of_another_game_version_is_rejected() {
+        let profile: LoaderProfile = serde_json::from_value(profile_json()).unwrap();
+        assert!(profile.merge_into(vanilla("1.20.2")).is_err());
+    }
+}
@@ -0,0 +1,645 @@
+//! Modern (1.13+) Forge installers. The installer jar ships `install_profile.json` describing
+//! libraries and processors, which patch the vanilla client, and the version json layered over
+//! the vanilla one.
+
+use std::{
+    collections::HashMap,
+    env,
+    fs::File,
+    io::{self, Read},
+    iter,
+    path::{Path, PathBuf},
+};
+
+use futures_util::{stream, StreamExt, TryStreamExt};
+use serde::de::DeserializeOwned;
+use serde_derive::Deserialize;
+use tokio::{fs, process::Command, task};
+use tracing::{instrument, trace};
+use url::Url;
+use zip::ZipArchive;
+
+use crate::{
+    io::{
+        download::Manager,
+        file::{create_temp, Hierarchy},
+    },
+    metadata::{
+        game::{Library, LibraryResource, LibraryResources, Resource, VersionInfo},
+        maven::MavenCoordinate,
+    },
+};
+
+use super::{Layer, LoaderArguments};
+
+pub static INSTALL_PROFILE_FILENAME: &str = "install_profile.json";
+pub static CLIENT_SIDE: &str = "client";
+
+#[derive(Deserialize, Debug)]
+pub struct ForgeArtifact {
+    pub path: String,
+    /// Empty for artifacts shipped in the installer or produced by processors.
+    pub url: String,
+    pub sha1: String,
+    pub size: u64,
+}
+
+impl ForgeArtifact {
+    /// Whether the file has the declared size and, with the `sha1` feature, checksum.
+    async fn is_valid(&self, local_path: &Path) -> crate::Result<bool> {
+        match fs::metadata(local_path).await {
+            Ok(metadata) if metadata.len() == self.size => {}
+            Ok(_) => return Ok(false),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e.into()),
+        }
+        #[cfg(feature = "sha1")]
+        if !self.sha1.is_empty()
+            && !crate::io::hash::Checksum::Sha1(self.sha1.clone())
+                .verify(local_path.to_path_buf())
+                .await?
+        {
+            return Ok(false);
+        }
+        Ok(true)
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ForgeDownloads {
+    pub artifact: ForgeArtifact,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ForgeLibrary {
+    pub name: String,
+    pub downloads: ForgeDownloads,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DataEntry {
+    pub client: String,
+    pub server: String,
+}
+
+/// Java program run by the installer, `jar` and `classpath` are maven coordinates.
+#[derive(Deserialize, Debug)]
+pub struct Processor {
+    /// Missing when the processor runs for every side.
+    pub sides: Option<Vec<String>>,
+    pub jar: String,
+    #[serde(default)]
+    pub classpath: Vec<String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Produced files mapped to their expected sha1, both may reference the data.
+    #[serde(default)]
+    pub outputs: HashMap<String, String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct InstallProfile {
+    pub spec: Option<u32>,
+    pub version: String,
+    pub minecraft: String,
+    /// Path of the version json inside the installer.
+    pub json: String,
+    #[serde(default)]
+    pub data: HashMap<String, DataEntry>,
+    #[serde(default)]
+    pub processors: Vec<Processor>,
+    #[serde(default)]
+    pub libraries: Vec<ForgeLibrary>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ForgeVersion {
+    pub id: String,
+    pub inherits_from: String,
+    pub main_class: String,
+    #[serde(default)]
+    pub arguments: LoaderArguments,
+    pub libraries: Vec<ForgeLibrary>,
+}
+
+#[derive(Debug)]
+pub struct Installer {
+    path: PathBuf,
+    profile: InstallProfile,
+    version: ForgeVersion,
+}
+
+impl Processor {
+    pub fn runs_on_client(&self) -> bool {
+        self.sides
+            .as_ref()
+            .map(|sides| sides.iter().any(|side| side == CLIENT_SIDE))
+            .unwrap_or(true)
+    }
+}
+
+impl ForgeLibrary {
+    /// Artifacts without an url aren't downloadable: they point to the local file instead, so
+    /// `libraries_dir` must be absolute, and pulling them fails with
+    /// [`crate::Error::MissingLocalArtifact`] until [`Installer::install`] produced them.
+    pub fn to_library(&self, libraries_dir: &Path) -> crate::Result<Library> {
+        let artifact = &self.downloads.artifact;
+        let url = if artifact.url.is_empty() {
+            Url::from_file_path(libraries_dir.join(&artifact.path)).map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "libraries dir must be absolute",
+                )
+            })?
+        } else {
+            Url::parse(&artifact.url)?
+        };
+        Ok(Library {
+            resources: LibraryResources {
+                artifact: Some(LibraryResource {
+                    resource: Resource {
+                        sha1: artifact.sha1.clone(),
+                        size: artifact.size,
+                        url,
+                    },
+                    path: artifact.path.clone(),
+                }),
+                other: None,
+            },
+            name: self.name.clone(),
+            rules: None,
+            natives: None,
+            url: None,
+        })
+    }
+}
+
+fn read_entry<T: DeserializeOwned>(archive: &mut ZipArchive<File>, name: &str) -> crate::Result<T> {
+    let entry = archive.by_name(name.trim_start_matches('/'))?;
+    Ok(
+        serde_json::from_reader(entry)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+    )
+}
+
+fn library_path(libraries_dir: &Path, name: &str) -> crate::Result<PathBuf> {
+    Ok(libraries_dir.join(MavenCoordinate::try_parse(name)?.path()))
+}
+
+/// Substitutes `[coordinate]` with the library path and `{KEY}` with the data value.
+fn resolve_arg(
+    arg: &str,
+    data: &HashMap<String, String>,
+    libraries_dir: &Path,
+) -> crate::Result<String> {
+    if let Some(name) = arg.strip_prefix('[').and_then(|arg| arg.strip_suffix(']')) {
+        return Ok(library_path(libraries_dir, name)?
+            .to_string_lossy()
+            .into_owned());
+    }
+    if let Some(key) = arg.strip_prefix('{').and_then(|arg| arg.strip_suffix('}')) {
+        return data.get(key).cloned().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unknown processor data: {}", key),
+            )
+            .into()
+        });
+    }
+    Ok(arg.to_owned())
+}
+
+/// `Main-Class` attribute of the jar's manifest.
+fn main_class_of(jar: &Path) -> crate::Result<String> {
+    let mut archive = ZipArchive::new(File::open(jar)?)?;
+    let mut manifest = String::new();
+    archive
+        .by_name("META-INF/MANIFEST.MF")?
+        .read_to_string(&mut manifest)?;
+    manifest
+        .lines()
+        .find_map(|line| line.strip_prefix("Main-Class:"))
+        .map(|main_class| main_class.trim().to_owned())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} has no main class", jar.display()),
+            )
+            .into()
+        })
+}
+
+impl Installer {
+    #[instrument]
+    pub async fn read(path: PathBuf) -> crate::Result<Self> {
+        let archive_path = path.clone();
+        let (profile, version) = task::spawn_blocking(move || -> crate::Result<_> {
+            let mut archive = ZipArchive::new(File::open(archive_path)?)?;
+            let profile: InstallProfile = read_entry(&mut archive, INSTALL_PROFILE_FILENAME)?;
+            let version: ForgeVersion = read_entry(&mut archive, &profile.json)?;
+            Ok((profile, version))
+        })
+        .await??;
+
+        Ok(Self {
+            path,
+            profile,
+            version,
+        })
+    }
+
+    pub fn profile(&self) -> &InstallProfile {
+        &self.profile
+    }
+
+    pub fn version(&self) -> &ForgeVersion {
+        &self.version
+    }
+
+    /// Processors run when installing the client, in order.
+    pub fn client_processors(&self) -> impl Iterator<Item = &Processor> {
+        self.profile
+            .processors
+            .iter()
+            .filter(|processor| processor.runs_on_client())
+    }
+
+    /// Layers the Forge version json over `vanilla`, the installer is done with afterwards. See
+    /// [`Self::install`] for the files needed to launch it.
+    pub fn merge_into(
+        self,
+        vanilla: VersionInfo,
+        hierarchy: &Hierarchy,
+    ) -> crate::Result<VersionInfo> {
+        let version = self.version;
+        let libraries = version
+            .libraries
+            .iter()
+            .map(|lib| lib.to_library(&hierarchy.libraries_dir))
+            .collect::<crate::Result<Vec<_>>>()?;
+        Layer {
+            id: version.id,
+            inherits_from: version.inherits_from,
+            main_class: version.main_class,
+            arguments: version.arguments,
+            libraries,
+        }
+        .merge_into(vanilla)
+    }
+
+    /// Extracts the libraries shipped in the installer, downloads the rest and runs the client
+    /// processors with `java_path`. `minecraft_jar` is the vanilla client the processors patch.
+    #[instrument(skip(self, downloader))]
+    pub async fn install(
+        &self,
+        downloader: &Manager,
+        hierarchy: &Hierarchy,
+        minecraft_jar: &Path,
+        java_path: &Path,
+        concurrency: usize,
+    ) -> crate::Result<()> {
+        let libraries_dir = &hierarchy.libraries_dir;
+        let (work_dir, ()) = create_temp("-forge", |path| std::fs::create_dir(path))?;
+        let result = async {
+            self.extract_maven(libraries_dir.clone()).await?;
+            self.download_libraries(downloader, libraries_dir, concurrency)
+                .await?;
+            let data = self
+                .resolve_data(hierarchy, minecraft_jar, &work_dir)
+                .await?;
+            for processor in self.client_processors() {
+                self.run_processor(processor, java_path, &data, libraries_dir)
+                    .await?;
+            }
+            Ok(())
+        }
+        .await;
+        if work_dir.exists() {
+            fs::remove_dir_all(&work_dir).await?;
+        }
+        result
+    }
+
+    /// Copies `maven/` of the installer, holding Forge's own jars, into the libraries dir.
+    async fn extract_maven(&self, libraries_dir: PathBuf) -> crate::Result<()> {
+        let archive_path = self.path.clone();
+        task::spawn_blocking(move || -> crate::Result<()> {
+            let mut archive = ZipArchive::new(File::open(archive_path)?)?;
+            for i in 0..archive.len() {
+                let mut entry = archive.by_index(i)?;
+                let relative = match entry.enclosed_name() {
+                    Some(name) if !entry.is_dir() => match name.strip_prefix("maven") {
+                        Ok(relative) => relative.to_path_buf(),
+                        Err(_) => continue,
+                    },
+                    _ => continue,
+                };
+                let output_path = libraries_dir.join(relative);
+                trace!(?output_path, "Extracting installer library");
+                if let Some(parent) = output_path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                io::copy(&mut entry, &mut File::create(output_path)?)?;
+            }
+            Ok(())
+        })
+        .await?
+    }
+
+    async fn download_libraries(
+        &self,
+        downloader: &Manager,
+        libraries_dir: &Path,
+        concurrency: usize,
+    ) -> crate::Result<()> {
+        stream::iter(
+            self.profile
+                .libraries
+                .iter()
+                .chain(&self.version.libraries)
+                .map(|lib| &lib.downloads.artifact)
+                .filter(|artifact| !artifact.url.is_empty()),
+        )
+        .map(Ok)
+        .try_for_each_concurrent(concurrency, |artifact| async move {
+            let local_path = libraries_dir.join(&artifact.path);
+            if artifact.is_valid(&local_path).await? {
+                return Ok(());
+            }
+            downloader
+                .download_file(artifact.url.as_str(), &local_path)
+                .await?;
+            if !artifact.is_valid(&local_path).await? {
+                return Err(crate::Error::ChecksumMismatch(local_path));
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// Client values of the profile data, along with the built-in entries. Files referenced
+    /// from the installer are extracted to `work_dir`.
+    async fn resolve_data(
+        &self,
+        hierarchy: &Hierarchy,
+        minecraft_jar: &Path,
+        work_dir: &Path,
+    ) -> crate::Result<HashMap<String, String>> {
+        let libraries_dir = &hierarchy.libraries_dir;
+        let mut data = HashMap::new();
+        for (key, entry) in &self.profile.data {
+            let value = &entry.client;
+            let resolved = if let Some(literal) = value
+                .strip_prefix('\'')
+                .and_then(|value| value.strip_suffix('\''))
+            {
+                literal.to_owned()
+            } else if value.starts_with('/') {
+                let output_path = work_dir.join(value.trim_start_matches('/'));
+                self.extract_file(value.clone(), output_path.clone())
+                    .await?;
+                output_path.to_string_lossy().into_owned()
+            } else {
+                resolve_arg(value, &HashMap::new(), libraries_dir)?
+            };
+            data.insert(key.clone(), resolved);
+        }
+        let builtins = [
+            ("SIDE", CLIENT_SIDE.to_owned()),
+            ("MINECRAFT_VERSION", self.profile.minecraft.clone()),
+            (
+                "MINECRAFT_JAR",
+                minecraft_jar.to_string_lossy().into_owned(),
+            ),
+            ("ROOT", hierarchy.gamedir.to_string_lossy().into_owned()),
+            ("INSTALLER", self.path.to_string_lossy().into_owned()),
+            ("LIBRARY_DIR", libraries_dir.to_string_lossy().into_owned()),
+        ];
+        data.extend(
+            builtins
+                .into_iter()
+                .map(|(key, value)| (key.to_owned(), value)),
+        );
+        trace!(?data, "Resolved processor data");
+        Ok(data)
+    }
+
+    async fn extract_file(&self, name: String, output_path: PathBuf) -> crate::Result<()> {
+        let archive_path = self.path.clone();
+        task::spawn_blocking(move || -> crate::Result<()> {
+            let mut archive = ZipArchive::new(File::open(archive_path)?)?;
+            let mut entry = archive.by_name(name.trim_start_matches('/'))?;
+            if let Some(parent) = output_path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            io::copy(&mut entry, &mut File::create(output_path)?)?;
+            Ok(())
+        })
+        .await?
+    }
+
+    #[instrument(skip(self, data))]
+    async fn run_processor(
+        &self,
+        processor: &Processor,
+        java_path: &Path,
+        data: &HashMap<String, String>,
+        libraries_dir: &Path,
+    ) -> crate::Result<()> {
+        let jar = library_path(libraries_dir, &processor.jar)?;
+        let main_class = {
+            let jar = jar.clone();
+            task::spawn_blocking(move || main_class_of(&jar)).await??
+        };
+        let classpath = iter::once(Ok(jar))
+            .chain(
+                processor
+                    .classpath
+                    .iter()
+                    .map(|name| library_path(libraries_dir, name)),
+            )
+            .collect::<crate::Result<Vec<_>>>()?;
+        let args = processor
+            .args
+            .iter()
+            .map(|arg| resolve_arg(arg, data, libraries_dir))
+            .collect::<crate::Result<Vec<_>>>()?;
+        trace!(%main_class, ?args, "Running processor");
+        let status = Command::new(java_path)
+            .arg("-cp")
+            .arg(env::join_paths(classpath)?)
+            .arg(main_class)
+            .args(args)
+            .status()
+            .await?;
+        if !status.success() {
+            return Err(crate::Error::ProcessorFailed {
+                jar: processor.jar.clone(),
+                status,
+            });
+        }
+        #[cfg(feature = "sha1")]
+        for (path, sha1) in &processor.outputs {
+            let path = PathBuf::from(resolve_arg(path, data, libraries_dir)?);
+            let sha1 = resolve_arg(sha1, data, libraries_dir)?;
+            let checksum = crate::io::hash::Checksum::Sha1(sha1.trim_matches('\'').to_owned());
+            if !checksum.verify(path.clone()).await? {
+                return Err(crate::Error::ChecksumMismatch(path));
+            }
+            trace!(?path, "Verified processor output");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        io::sync::{FetchOptions, RemoteRepository},
+        testing::{temp_dir, version_json, zip, MockServer},
+    };
+
+    static FORGE_JAR: &str =
+        "net/minecraftforge/forge/1.20.4-49.0.3/forge-1.20.4-49.0.3-client.jar";
+
+    /// Installer jar whose client processors are `installertools` then `binarypatcher`, and
+    /// whose version ships a client jar produced by them.
+    fn write_installer(dir: &Path) -> PathBuf {
+        let profile = json!({
+            "spec": 1,
+            "version": "1.20.4-forge-49.0.3",
+            "minecraft": "1.20.4",
+            "json": "/version.json",
+            "data": {
+                "MAPPINGS": {
+                    "client": "[de.oceanlabs.mcp:mcp_config:1.20.4:mappings@txt]",
+                    "server": "[de.oceanlabs.mcp:mcp_config:1.20.4:mappings@txt]",
+                },
+            },
+            "processors": [
+                {
+                    "jar": "net.minecraftforge:installertools:1.3.0",
+                    "args": ["--task", "MCP_DATA", "--output", "{MAPPINGS}"],
+                },
+                {
+                    "sides": ["server"],
+                    "jar": "net.minecraftforge:jarsplitter:1.1.4",
+                },
+                {
+                    "sides": ["client"],
+                    "jar": "net.minecraftforge:binarypatcher:1.1.1",
+                    "classpath": ["net.sf.jopt-simple:jopt-simple:5.0.4"],
+                    "args": ["--clean", "{MINECRAFT_JAR}"],
+                },
+            ],
+        });
+        let version = json!({
+            "id": "1.20.4-forge-49.0.3",
+            "inheritsFrom": "1.20.4",
+            "mainClass": "cpw.mods.bootstraplauncher.BootstrapLauncher",
+            "libraries": [{
+                "name": "net.minecraftforge:forge:1.20.4-49.0.3:client",
+                "downloads": {
+                    "artifact": {
+                        "path": FORGE_JAR,
+                        "url": "",
+                        "sha1": "",
+                        "size": 0,
+                    },
+                },
+            }],
+        });
+        let path = dir.join("forge-installer.jar");
+        std::fs::write(
+            &path,
+            zip(&[
+                (INSTALL_PROFILE_FILENAME, profile.to_string().as_bytes()),
+                ("version.json", version.to_string().as_bytes()),
+            ]),
+        )
+        .unwrap();
+        path
+    }
+
+    #[tokio::test]
+    async fn install_profile_lists_client_processors() {
+        let dir = temp_dir();
+        let installer = Installer::read(write_installer(&dir)).await.unwrap();
+
+        assert_eq!(installer.profile().minecraft, "1.20.4");
+        let processors: Vec<_> = installer
+            .client_processors()
+            .map(|processor| (processor.jar.as_str(), processor.args.len()))
+            .collect();
+        assert_eq!(
+            processors,
+            [
+                ("net.minecraftforge:installertools:1.3.0", 4),
+                ("net.minecraftforge:binarypatcher:1.1.1", 2),
+            ]
+        );
+        let mappings = &installer.profile().data["MAPPINGS"].client;
+        assert_eq!(
+            resolve_arg(mappings, &HashMap::new(), Path::new("libraries")).unwrap(),
+            Path::new("libraries")
+                .join("de/oceanlabs/mcp/mcp_config/1.20.4/mcp_config-1.20.4-mappings.txt")
+                .to_string_lossy()
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn produced_artifact_is_not_downloaded() {
+        let dir = temp_dir();
+        let hierarchy = Hierarchy::builder(&dir, "1.20.4-forge-49.0.3").build();
+        let server = MockServer::start().await;
+        server.set("client.jar", "client");
+        let mut vanilla = version_json(&server.url(""));
+        vanilla["id"] = "1.20.4".into();
+        let installer = Installer::read(write_installer(&dir)).await.unwrap();
+        let info = installer
+            .merge_into(serde_json::from_value(vanilla).unwrap(), &hierarchy)
+            .unwrap();
+        let options = FetchOptions {
+            skip_assets: true,
+            ..Default::default()
+        };
+        let repository = RemoteRepository::from_version_info(
+            &Manager::default(),
+            &hierarchy,
+            info,
+            &HashMap::new(),
+            &options,
+        )
+        .await
+        .unwrap();
+        let forge_jar = hierarchy.libraries_dir.join(FORGE_JAR);
+
+        match repository
+            .track_invalid()
+            .await
+            .unwrap()
+            .pull(&Manager::default(), 1)
+            .await
+        {
+            Err(crate::Error::MissingLocalArtifact(path)) => assert_eq!(path, forge_jar),
+            other => panic!("expected a missing local artifact, got {:?}", other),
+        }
+
+        // as if the processors ran
+        std::fs::create_dir_all(forge_jar.parent().unwrap()).unwrap();
+        std::fs::write(&forge_jar, "patched").unwrap();
+        repository
+            .track_invalid()
+            .await
+            .unwrap()
+            .pull(&Manager::default(), 1)
+            .await
+            .unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
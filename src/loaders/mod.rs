@@ -0,0 +1,201 @@
+//! Mod loaders shipping their version json as a profile layered over a vanilla version. The
+//! vanilla info is fetched as usual, merged with the profile and turned into a repository with
+//! [`RemoteRepository::from_version_info`](crate::io::sync::RemoteRepository::from_version_info).
+
+use std::{collections::HashSet, io};
+
+use reqwest::Client;
+use serde::de::DeserializeOwned;
+use serde_derive::Deserialize;
+use tracing::{instrument, trace};
+use url::Url;
+
+use crate::metadata::{
+    game::{
+        Argument, Arguments, Library, LibraryResource, LibraryResources, Resource, VersionInfo,
+    },
+    maven::MavenCoordinate,
+};
+
+pub mod fabric;
+pub mod forge;
+pub mod quilt;
+
+#[derive(Deserialize, Debug)]
+pub struct LoaderArtifact {
+    pub maven: String,
+    pub version: String,
+    /// Missing for loaders not marking stable builds.
+    pub stable: Option<bool>,
+}
+
+/// Entry of the loader versions list compatible with a game version.
+#[derive(Deserialize, Debug)]
+pub struct LoaderVersion {
+    pub loader: LoaderArtifact,
+}
+
+/// Library hosted in a maven repository at `url`. Checksum and size are missing in old profiles.
+#[derive(Deserialize, Debug)]
+pub struct MavenLibrary {
+    pub name: String,
+    pub url: Url,
+    pub sha1: Option<String>,
+    pub size: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct LoaderArguments {
+    #[serde(default)]
+    pub game: Vec<Argument>,
+    #[serde(default)]
+    pub jvm: Vec<Argument>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LoaderProfile {
+    pub id: String,
+    pub inherits_from: String,
+    pub main_class: String,
+    #[serde(default)]
+    pub arguments: LoaderArguments,
+    pub libraries: Vec<MavenLibrary>,
+}
+
+/// `group:artifact`, libraries of the same key are different versions of one artifact.
+fn artifact_key(name: &str) -> Option<String> {
+    let coordinate = MavenCoordinate::parse(name)?;
+    Some(format!("{}:{}", coordinate.group, coordinate.artifact))
+}
+
+impl MavenLibrary {
+    pub fn to_library(&self) -> crate::Result<Library> {
+        let coordinate = MavenCoordinate::try_parse(&self.name)?;
+        Ok(Library {
+            resources: LibraryResources {
+                artifact: Some(LibraryResource {
+                    resource: Resource {
+                        sha1: self.sha1.clone().unwrap_or_default(),
+                        size: self.size.unwrap_or(0),
+                        url: coordinate.url(&self.url)?,
+                    },
+                    path: coordinate.path(),
+                }),
+                other: None,
+            },
+            name: self.name.clone(),
+            rules: None,
+            natives: None,
+            url: Some(self.url.clone()),
+        })
+    }
+}
+
+/// Parts of a loader version json layered over the vanilla one. Loader libraries go first and
+/// replace vanilla ones of the same artifact.
+struct Layer {
+    id: String,
+    inherits_from: String,
+    main_class: String,
+    arguments: LoaderArguments,
+    libraries: Vec<Library>,
+}
+
+impl Layer {
+    fn merge_into(self, mut vanilla: VersionInfo) -> crate::Result<VersionInfo> {
+        if self.inherits_from != vanilla.id {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "profile inherits from {}, not {}",
+                    self.inherits_from, vanilla.id
+                ),
+            )
+            .into());
+        }
+        let mut libraries = self.libraries;
+        let overridden: HashSet<_> = libraries
+            .iter()
+            .filter_map(|lib| artifact_key(&lib.name))
+            .collect();
+        vanilla.libraries.retain(|lib| {
+            artifact_key(&lib.name)
+                .map(|key| !overridden.contains(&key))
+                .unwrap_or(true)
+        });
+        libraries.append(&mut vanilla.libraries);
+        vanilla.libraries = libraries;
+
+        match &mut vanilla.arguments {
+            Arguments::Modern { game, jvm } => {
+                game.extend(self.arguments.game);
+                jvm.extend(self.arguments.jvm);
+            }
+            Arguments::Legacy(game) => {
+                game.extend(self.arguments.game.into_iter().filter_map(|arg| match arg {
+                    Argument::Plain(arg) => Some(arg),
+                    Argument::RuleSpecific { .. } => None,
+                }));
+            }
+        }
+        vanilla.id = self.id;
+        vanilla.main_class = self.main_class;
+        Ok(vanilla)
+    }
+}
+
+impl LoaderProfile {
+    /// Layers the profile over `vanilla`, the version it inherits from.
+    pub fn merge_into(self, vanilla: VersionInfo) -> crate::Result<VersionInfo> {
+        let libraries = self
+            .libraries
+            .iter()
+            .map(MavenLibrary::to_library)
+            .collect::<crate::Result<Vec<_>>>()?;
+        Layer {
+            id: self.id,
+            inherits_from: self.inherits_from,
+            main_class: self.main_class,
+            arguments: self.arguments,
+            libraries,
+        }
+        .merge_into(vanilla)
+    }
+}
+
+async fn fetch_json<T: DeserializeOwned>(client: &Client, url: &str) -> crate::Result<T> {
+    Ok(client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?)
+}
+
+#[instrument(skip(client))]
+async fn fetch_loader_versions(
+    client: &Client,
+    meta_url: &str,
+    game_version: &str,
+) -> crate::Result<Vec<LoaderVersion>> {
+    let url = format!("{}/versions/loader/{}", meta_url, game_version);
+    let versions: Vec<LoaderVersion> = fetch_json(client, &url).await?;
+    trace!(count = versions.len(), "Fetched loader versions");
+    Ok(versions)
+}
+
+#[instrument(skip(client))]
+async fn fetch_profile(
+    client: &Client,
+    meta_url: &str,
+    game_version: &str,
+    loader_version: &str,
+) -> crate::Result<LoaderProfile> {
+    let url = format!(
+        "{}/versions/loader/{}/{}/profile/json",
+        meta_url, game_version, loader_version
+    );
+    fetch_json(client, &url).await
+}
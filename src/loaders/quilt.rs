@@ -0,0 +1,95 @@
+use reqwest::Client;
+
+use super::{LoaderProfile, LoaderVersion};
+
+pub static META_URL: &str = "https://meta.quiltmc.org/v3";
+
+/// Quilt loader versions supporting `game_version`, newest first. Quilt doesn't flag stable
+/// builds, pre-releases are told apart by their `-beta` version suffix.
+pub async fn fetch_loader_versions(
+    client: &Client,
+    game_version: &str,
+) -> crate::Result<Vec<LoaderVersion>> {
+    super::fetch_loader_versions(client, META_URL, game_version).await
+}
+
+/// Launcher profile of a Quilt loader version, its libraries come from both the Quilt and the
+/// Fabric mavens.
+pub async fn fetch_profile(
+    client: &Client,
+    game_version: &str,
+    loader_version: &str,
+) -> crate::Result<LoaderProfile> {
+    super::fetch_profile(client, META_URL, game_version, loader_version).await
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use url::Url;
+
+    use super::*;
+    use crate::testing::{version_json, MockServer};
+
+    #[tokio::test]
+    async fn profile_resolves_main_class_and_libraries() {
+        let server = MockServer::start().await;
+        let profile = json!({
+            "id": "quilt-loader-0.23.1-1.20.4",
+            "inheritsFrom": "1.20.4",
+            "type": "release",
+            "mainClass": "org.quiltmc.loader.impl.launch.knot.KnotClient",
+            "arguments": { "game": [] },
+            "libraries": [
+                {
+                    "name": "net.fabricmc:sponge-mixin:0.12.5+mixin.0.8.5",
+                    "url": "https://maven.fabricmc.net/",
+                },
+                {
+                    "name": "org.quiltmc:quilt-loader:0.23.1",
+                    "url": "https://maven.quiltmc.org/repository/release/",
+                },
+            ],
+        });
+        server.set(
+            "v3/versions/loader/1.20.4/0.23.1/profile/json",
+            profile.to_string(),
+        );
+        let meta_url = server.url("v3");
+        let profile =
+            super::super::fetch_profile(&Client::new(), meta_url.as_str(), "1.20.4", "0.23.1")
+                .await
+                .unwrap();
+        let mut vanilla = version_json(&Url::parse("https://example.invalid/").unwrap());
+        vanilla["id"] = "1.20.4".into();
+
+        let merged = profile
+            .merge_into(serde_json::from_value(vanilla).unwrap())
+            .unwrap();
+        assert_eq!(merged.id, "quilt-loader-0.23.1-1.20.4");
+        assert_eq!(
+            merged.main_class,
+            "org.quiltmc.loader.impl.launch.knot.KnotClient"
+        );
+        let urls: Vec<_> = merged
+            .libraries
+            .iter()
+            .map(|lib| {
+                lib.resources
+                    .artifact
+                    .as_ref()
+                    .unwrap()
+                    .resource
+                    .url
+                    .as_str()
+            })
+            .collect();
+        assert_eq!(
+            urls,
+            [
+                "https://maven.fabricmc.net/net/fabricmc/sponge-mixin/0.12.5+mixin.0.8.5/sponge-mixin-0.12.5+mixin.0.8.5.jar",
+                "https://maven.quiltmc.org/repository/release/org/quiltmc/quilt-loader/0.23.1/quilt-loader-0.23.1.jar",
+            ]
+        );
+    }
+}
@@ -0,0 +1,78 @@
+use futures_util::{stream, StreamExt};
+use reqwest::Client;
+use tracing::{instrument, warn};
+
+use crate::{
+    metadata::{game::VersionInfo, manifest::VersionsManifest},
+    resources::{fetch_manifest, fetch_version_info},
+};
+
+/// Version jsons fetched at once by [`parse_versions`].
+pub const CONCURRENCY: usize = 16;
+
+/// Fetches and parses the json of every version listed by the manifest, so schema changes
+/// breaking deserialization are reported per version id instead of surfacing at launch.
+#[instrument(skip(client))]
+pub async fn parse_all_versions(
+    client: &Client,
+) -> crate::Result<Vec<(String, crate::Result<VersionInfo>)>> {
+    let manifest = fetch_manifest(client).await?;
+    Ok(parse_versions(client, manifest).await)
+}
+
+/// Same as [`parse_all_versions`], for the versions of a given manifest, e.g. of a mirror.
+/// Results come in completion order.
+#[instrument(skip_all)]
+pub async fn parse_versions(
+    client: &Client,
+    manifest: VersionsManifest,
+) -> Vec<(String, crate::Result<VersionInfo>)> {
+    stream::iter(manifest.versions)
+        .map(|version| async move {
+            let result = fetch_version_info(client, version.url).await;
+            if let Err(e) = &result {
+                warn!(id = %version.id, %e, "Version can't be parsed");
+            }
+            (version.id, result)
+        })
+        .buffer_unordered(CONCURRENCY)
+        .collect()
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::testing::{version_json, MockServer};
+
+    #[tokio::test]
+    async fn parse_failures_are_reported_per_version() {
+        let server = MockServer::start().await;
+        server.set("1.12.2.json", version_json(&server.url("")).to_string());
+        server.set("1.13.json", r#"{"id": "1.13", "arguments": 42}"#);
+        let version = |id: &str| {
+            json!({
+                "id": id,
+                "type": "release",
+                "url": server.url(&format!("{}.json", id)),
+                "time": "2017-09-18T08:39:46+00:00",
+                "releaseTime": "2017-09-18T08:39:46+00:00",
+            })
+        };
+        let manifest: VersionsManifest = serde_json::from_value(json!({
+            "latest": { "release": "1.13", "snapshot": "1.13" },
+            "versions": [version("1.13"), version("1.12.2"), version("missing")],
+        }))
+        .unwrap();
+
+        let mut results = parse_versions(&Client::new(), manifest).await;
+        results.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let ids: Vec<_> = results.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(ids, ["1.12.2", "1.13", "missing"]);
+        assert_eq!(results[0].1.as_ref().unwrap().id, "1.12.2");
+        assert!(results[1].1.is_err());
+        assert!(results[2].1.is_err());
+    }
+}
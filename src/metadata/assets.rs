@@ -1,20 +1,44 @@
 use std::collections::HashMap;
 
-use serde_derive::Deserialize;
+use serde_derive::{Deserialize, Serialize};
+
+/// Placeholder for assets declared without a hash, such objects can't be downloaded.
+pub const EMPTY_HASH: &str = "00null";
 
 fn empty_hash() -> String {
-    String::from("00null")
+    String::from(EMPTY_HASH)
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct AssetMetadata {
     #[serde(default = "empty_hash")]
     pub hash: String,
     pub size: u64,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct AssetIndex {
+    /// Set by the pre-1.6 index, objects are stored by name in the game's `resources/` dir.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub map_to_resources: Option<bool>,
+    /// Set by the legacy index, objects are stored by name in `virtual/legacy/`.
+    #[serde(rename = "virtual", skip_serializing_if = "Option::is_none")]
+    pub is_virtual: Option<bool>,
     pub objects: HashMap<String, AssetMetadata>,
 }
+
+impl AssetIndex {
+    pub fn maps_to_resources(&self) -> bool {
+        self.map_to_resources.unwrap_or(false)
+    }
+
+    pub fn is_virtual(&self) -> bool {
+        self.is_virtual.unwrap_or(false)
+    }
+}
+
+impl AssetMetadata {
+    pub fn has_hash(&self) -> bool {
+        self.hash != EMPTY_HASH
+    }
+}
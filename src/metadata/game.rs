@@ -1,50 +1,67 @@
-use std::{collections::HashMap, env::consts, iter};
+use std::{
+    collections::{HashMap, HashSet},
+    env::consts,
+    io, iter,
+    path::{Path, PathBuf},
+};
 
 use chrono::{DateTime, Utc};
-use serde_derive::Deserialize;
+use serde::{de::Deserialize as _, Deserializer};
+use serde_derive::{Deserialize, Serialize};
 use serde_with::{serde_as, OneOrMany, SpaceSeparator, StringWithSeparator};
+use tokio::fs;
+use tracing::{instrument, trace};
 use url::Url;
 
-use super::manifest::ReleaseType;
+use crate::io::{file::Hierarchy, hash::Checksum};
 
-#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+use super::{assets::AssetIndex, manifest::ReleaseType, maven::MavenCoordinate};
+
+pub static INFO_FILENAME: &str = "info.json";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum RuleAction {
     Allow,
     Disallow,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct OsDescription {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub arch: Option<String>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct Rule {
     pub action: RuleAction,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub os: Option<OsDescription>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub features: Option<HashMap<String, bool>>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct Rules(Vec<Rule>);
 
 #[serde_as]
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(untagged)]
 pub enum Argument {
     Plain(String),
     RuleSpecific {
-        #[serde_as(deserialize_as = "OneOrMany<_>")]
+        #[serde_as(as = "OneOrMany<_>")]
         value: Vec<String>,
         rules: Rules,
     },
 }
 
 #[serde_as]
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub enum Arguments {
     #[serde(rename = "arguments")]
     Modern {
@@ -55,14 +72,15 @@ pub enum Arguments {
     Legacy(#[serde_as(as = "StringWithSeparator::<SpaceSeparator, String>")] Vec<String>),
 }
 
-#[derive(Deserialize, Debug)]
+/// Remote file. `sha1` is empty and `size` is zero when unknown, e.g. for mod loader libraries.
+#[derive(Serialize, Deserialize, Debug)]
 pub struct Resource {
     pub sha1: String,
     pub size: u64,
     pub url: Url,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct AssetIndexResource {
     #[serde(flatten)]
@@ -71,14 +89,14 @@ pub struct AssetIndexResource {
     pub total_size: u64,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct LoggerConfig {
     #[serde(flatten)]
     pub resource: Resource,
     pub id: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct LoggerDescription {
     pub argument: String,
     #[serde(rename = "type")]
@@ -87,47 +105,86 @@ pub struct LoggerDescription {
     pub config: LoggerConfig,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct Logging {
     pub client: LoggerDescription,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct LibraryResource {
     #[serde(flatten)]
     pub resource: Resource,
     pub path: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct LibraryResources {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub artifact: Option<LibraryResource>,
     #[serde(rename = "classifiers")]
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub other: Option<HashMap<String, LibraryResource>>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct Library {
-    #[serde(rename = "downloads")]
+    #[serde(rename = "downloads", default)]
     pub resources: LibraryResources,
     pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub rules: Option<Rules>,
+    /// Classifier of the native artifact per OS, may contain `${arch}` (e.g.
+    /// `natives-windows-${arch}`) in versions before 1.19.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub natives: Option<HashMap<String, String>>,
+    /// Maven repository hosting the library when it has no `downloads`, e.g. in mod loader
+    /// profiles. See [`Library::resolve_maven_artifact`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<Url>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct JavaVersion {
     pub component: String,
     pub major_version: usize,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct Downloads {
     pub client: Resource,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub server: Option<Resource>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_mappings: Option<Resource>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub server_mappings: Option<Resource>,
+    /// Keys unknown to this crate, e.g. `windows_server` of old versions. Ones not shaped like a
+    /// resource are skipped.
+    #[serde(flatten, deserialize_with = "deserialize_other_downloads")]
+    pub other: HashMap<String, Resource>,
+}
+
+fn deserialize_other_downloads<'de, D>(
+    deserializer: D,
+) -> Result<HashMap<String, Resource>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let other = HashMap::<String, serde_json::Value>::deserialize(deserializer)?;
+    Ok(other
+        .into_iter()
+        .filter_map(|(key, value)| match serde_json::from_value(value) {
+            Ok(resource) => Some((key, resource)),
+            Err(e) => {
+                trace!(key, %e, "Skipping unknown download");
+                None
+            }
+        })
+        .collect())
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct VersionInfo {
     pub id: String,
@@ -144,11 +201,58 @@ pub struct VersionInfo {
     #[serde(flatten)]
     pub arguments: Arguments,
 
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub java_version: Option<JavaVersion>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub logging: Option<Logging>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub compliance_level: Option<usize>,
 }
 
+/// Library dropped by its rules, see [`VersionInfo::excluded_libraries`].
+#[derive(Debug)]
+pub struct LibraryExclusion<'a> {
+    pub name: &'a str,
+    /// First rule that disallowed the library.
+    pub rule: &'a Rule,
+}
+
+/// Platform rules are evaluated against. `os` and `arch` use Mojang's naming, e.g. `osx`, `x86`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    pub os: String,
+    pub arch: String,
+    pub version: Option<String>,
+}
+
+impl Downloads {
+    /// Download by its key in the version json, whether it's known to this crate or not.
+    pub fn get(&self, key: &str) -> Option<&Resource> {
+        match key {
+            "client" => Some(&self.client),
+            "server" => self.server.as_ref(),
+            "client_mappings" => self.client_mappings.as_ref(),
+            "server_mappings" => self.server_mappings.as_ref(),
+            _ => self.other.get(key),
+        }
+    }
+}
+
+impl Resource {
+    pub fn checksum(&self) -> Checksum {
+        if self.sha1.is_empty() {
+            Checksum::None
+        } else {
+            Checksum::Sha1(self.sha1.clone())
+        }
+    }
+
+    /// Whether a file of `len` bytes may be this resource.
+    pub fn size_matches(&self, len: u64) -> bool {
+        self.size == 0 || self.size == len
+    }
+}
+
 impl RuleAction {
     pub fn value(self) -> bool {
         match self {
@@ -165,20 +269,58 @@ impl RuleAction {
     }
 }
 
+impl JavaVersion {
+    /// Human-readable name of the runtime `component`, `None` for unknown ones.
+    pub fn description(&self) -> Option<&'static str> {
+        Some(match self.component.as_str() {
+            "jre-legacy" => "Java 8 (legacy)",
+            "java-runtime-alpha" => "Java 16",
+            "java-runtime-beta" => "Java 17",
+            "java-runtime-gamma" | "java-runtime-gamma-snapshot" => "Java 17",
+            "java-runtime-delta" => "Java 21",
+            "minecraft-java-exe" => "Minecraft bundled launcher runtime",
+            _ => return None,
+        })
+    }
+}
+
+impl Target {
+    /// Platform this crate is running on, with the OS named the way Mojang's rules do.
+    pub fn current() -> Self {
+        let os = match consts::OS {
+            "macos" => "osx",
+            os => os,
+        };
+        Self {
+            os: os.to_owned(),
+            arch: consts::ARCH.to_owned(),
+            version: None,
+        }
+    }
+
+    /// Substituted for `${arch}` in native classifiers.
+    pub fn bit_width(&self) -> &'static str {
+        match self.arch.as_str() {
+            "x86" | "arm" => "32",
+            _ => "64",
+        }
+    }
+}
+
 impl Rule {
-    fn calculate_action(&self, params: &HashMap<&str, bool>) -> RuleAction {
+    fn calculate_action(&self, target: &Target, params: &HashMap<&str, bool>) -> RuleAction {
         if let Some(os) = &self.os {
             if let Some(name) = &os.name {
-                if name != consts::OS {
+                if name != &target.os {
                     return self.action.invert();
                 }
             }
             if let Some(arch) = &os.arch {
-                if arch != consts::ARCH {
+                if arch != &target.arch {
                     return self.action.invert();
                 }
             }
-            if let Some(version) = &os.version {
+            if let Some(_version) = &os.version {
                 // TODO: version parsing using crate
             }
         }
@@ -193,13 +335,28 @@ impl Rule {
     }
 
     pub fn is_allowed(&self, params: &HashMap<&str, bool>) -> bool {
-        self.calculate_action(params).value()
+        self.is_allowed_for(&Target::current(), params)
+    }
+
+    pub fn is_allowed_for(&self, target: &Target, params: &HashMap<&str, bool>) -> bool {
+        self.calculate_action(target, params).value()
     }
 }
 
 impl Rules {
     pub fn is_allowed(&self, params: &HashMap<&str, bool>) -> bool {
-        !self.0.iter().any(|rule| !rule.is_allowed(params))
+        self.is_allowed_for(&Target::current(), params)
+    }
+
+    pub fn is_allowed_for(&self, target: &Target, params: &HashMap<&str, bool>) -> bool {
+        self.disallowed_by(target, params).is_none()
+    }
+
+    /// First rule disallowing the target, if any.
+    pub fn disallowed_by(&self, target: &Target, params: &HashMap<&str, bool>) -> Option<&Rule> {
+        self.0
+            .iter()
+            .find(|rule| !rule.is_allowed_for(target, params))
     }
 }
 
@@ -249,24 +406,704 @@ impl Arguments {
     }
 }
 
+impl VersionInfo {
+    pub fn path(hierarchy: &Hierarchy) -> PathBuf {
+        hierarchy.version_dir.join(INFO_FILENAME)
+    }
+
+    /// Resolves artifacts of libraries hosted in arbitrary maven repositories, see
+    /// [`Library::resolve_maven_artifact`].
+    pub fn resolve_maven_artifacts(&mut self) -> crate::Result<()> {
+        self.libraries
+            .iter_mut()
+            .try_for_each(Library::resolve_maven_artifact)
+    }
+
+    /// Runtime the version is built for. Missing for old versions, which run on Java 8.
+    pub fn required_java(&self) -> Option<&JavaVersion> {
+        self.java_version.as_ref()
+    }
+
+    /// Parses a version json, failing with [`crate::Error::UnsupportedVersionFormat`] if it has
+    /// no arguments of any known shape, e.g. one of a future format.
+    pub fn from_slice(filebuf: &[u8]) -> crate::Result<Self> {
+        serde_json::from_slice(filebuf).map_err(|e| {
+            // only looked into on failure, as it's a second parse
+            match serde_json::from_slice::<serde_json::Map<String, serde_json::Value>>(filebuf) {
+                Ok(object)
+                    if !object.contains_key("arguments")
+                        && !object.contains_key("minecraftArguments") =>
+                {
+                    crate::Error::UnsupportedVersionFormat {
+                        id: object
+                            .get("id")
+                            .and_then(serde_json::Value::as_str)
+                            .unwrap_or_default()
+                            .to_owned(),
+                    }
+                }
+                _ => io::Error::new(io::ErrorKind::InvalidData, e).into(),
+            }
+        })
+    }
+
+    /// Reads the version json saved by [`crate::io::sync::RemoteRepository::fetch`], without
+    /// any network access. Enough to build a launch command for an already installed version.
+    #[instrument]
+    pub async fn load(path: &Path) -> crate::Result<Self> {
+        let filebuf = fs::read(path).await?;
+        let mut info = Self::from_slice(&filebuf)?;
+        info.resolve_maven_artifacts()?;
+        Ok(info)
+    }
+
+    /// Fails with [`crate::Error::AssetIndexMismatch`] if the `assets` id differs from the asset
+    /// index one. The index is saved under the former, but downloaded as the latter.
+    pub fn check_asset_index(&self) -> crate::Result<()> {
+        if self.assets == self.asset_index.id {
+            Ok(())
+        } else {
+            Err(crate::Error::AssetIndexMismatch {
+                assets: self.assets.clone(),
+                asset_index: self.asset_index.id.clone(),
+            })
+        }
+    }
+
+    /// Jars to put on the classpath: rule-filtered library artifacts in declaration order, then
+    /// the client jar.
+    pub fn resolved_classpath(
+        &self,
+        hierarchy: &Hierarchy,
+        features: &HashMap<&str, bool>,
+    ) -> Vec<PathBuf> {
+        self.libraries
+            .iter()
+            .filter(|lib| lib.is_allowed(features))
+            .filter_map(|lib| lib.resources.artifact.as_ref())
+            .map(|artifact| hierarchy.libraries_dir.join(&artifact.path))
+            .chain(iter::once(hierarchy.version_dir.join("client.jar")))
+            .collect()
+    }
+
+    /// Bytes the version takes once installed for the target, as declared by its metadata: client
+    /// jar, libraries with natives, logging config and asset objects. Nothing is read from disk.
+    /// Objects without a hash are left out, as they can't be downloaded.
+    pub fn declared_size(
+        &self,
+        asset_index: &AssetIndex,
+        target: &Target,
+        features: &HashMap<&str, bool>,
+    ) -> u64 {
+        let libraries: u64 = self
+            .libraries
+            .iter()
+            .filter(|lib| lib.is_allowed_for(target, features))
+            .flat_map(|lib| {
+                lib.resources
+                    .artifact
+                    .iter()
+                    .chain(lib.get_native_for(target))
+            })
+            .map(|resource| resource.resource.size)
+            .sum();
+        let logging = self
+            .logging
+            .as_ref()
+            .map(|logging| logging.client.config.resource.size)
+            .unwrap_or(0);
+        // like when pulled: objects shared by several names are stored once, unless legacy
+        let is_legacy_assets = asset_index.maps_to_resources() || asset_index.is_virtual();
+        let mut unique_hashes = HashSet::with_capacity(asset_index.objects.len());
+        let assets: u64 = asset_index
+            .objects
+            .values()
+            .filter(|obj| obj.has_hash() && (is_legacy_assets || unique_hashes.insert(&obj.hash)))
+            .map(|obj| obj.size)
+            .sum();
+        self.downloads.client.size + libraries + logging + assets
+    }
+
+    /// Libraries dropped by their rules for the target, along with the deciding rule.
+    pub fn excluded_libraries<'a>(
+        &'a self,
+        target: &Target,
+        features: &HashMap<&str, bool>,
+    ) -> Vec<LibraryExclusion<'a>> {
+        self.libraries
+            .iter()
+            .filter_map(|lib| {
+                let rule = lib.rules.as_ref()?.disallowed_by(target, features)?;
+                Some(LibraryExclusion {
+                    name: &lib.name,
+                    rule,
+                })
+            })
+            .collect()
+    }
+}
+
 impl Library {
     pub fn is_supported_by_rules(&self) -> bool {
+        self.is_allowed(&HashMap::new())
+    }
+
+    pub fn is_allowed(&self, features: &HashMap<&str, bool>) -> bool {
+        self.is_allowed_for(&Target::current(), features)
+    }
+
+    pub fn is_allowed_for(&self, target: &Target, features: &HashMap<&str, bool>) -> bool {
         self.rules
             .as_ref()
-            .map(|rules| rules.is_allowed(&HashMap::new()))
+            .map(|rules| rules.is_allowed_for(target, features))
             .unwrap_or(true)
     }
+
+    /// Fills the artifact of a library declaring only its maven `name` and repository `url`. Its
+    /// size and checksum stay unknown.
+    pub fn resolve_maven_artifact(&mut self) -> crate::Result<()> {
+        let repository = match &self.url {
+            Some(url) if self.resources.artifact.is_none() && self.resources.other.is_none() => url,
+            _ => return Ok(()),
+        };
+        let coordinate = MavenCoordinate::try_parse(&self.name)?;
+        self.resources.artifact = Some(LibraryResource {
+            resource: Resource {
+                sha1: String::new(),
+                size: 0,
+                url: coordinate.url(repository)?,
+            },
+            path: coordinate.path(),
+        });
+        Ok(())
+    }
+
+    /// Classifier declared in `natives` for the target's OS, with `${arch}` replaced by its
+    /// bit-width.
+    pub fn native_classifier_for(&self, target: &Target) -> Option<String> {
+        let classifier = self.natives.as_ref()?.get(&target.os)?;
+        Some(classifier.replace("${arch}", target.bit_width()))
+    }
+
+    /// Native artifact for the target. The classifier declared in `natives` is preferred, others
+    /// are looked up by their well-known names.
+    pub fn get_native_for(&self, target: &Target) -> Option<&LibraryResource> {
+        let key = self.chosen_native_key_for(target)?;
+        self.resources.other.as_ref()?.get(key)
+    }
+
+    /// Native artifact for the host platform, see [`Self::get_native_for`].
+    pub fn get_native_for_os(&self) -> Option<&LibraryResource> {
+        self.get_native_for(&Target::current())
+    }
+
+    /// Classifier of the native picked for the host platform, useful for diagnostics.
+    pub fn chosen_native_key(&self) -> Option<&str> {
+        self.chosen_native_key_for(&Target::current())
+    }
+
+    /// Classifier of the native artifact picked for the target, see [`Self::get_native_for`].
+    pub fn chosen_native_key_for(&self, target: &Target) -> Option<&str> {
+        match self.native_classifier_for(target) {
+            Some(classifier) => self
+                .resources
+                .other
+                .as_ref()?
+                .get_key_value(&classifier)
+                .map(|(key, _)| key.as_str()),
+            None => self.resources.well_known_native_key_for(target),
+        }
+    }
 }
 
 impl LibraryResources {
+    #[deprecated(note = "ignores the declared classifier, use `Library::get_native_for_os`")]
     pub fn get_native_for_os(&self) -> Option<&LibraryResource> {
-        let native_str: &'static str = match consts::OS {
-            "macos" if consts::ARCH == "aarch64" => "natives-macos-arm64",
+        self.well_known_native_for(&Target::current())
+    }
+
+    #[deprecated(note = "ignores the declared classifier, use `Library::get_native_for`")]
+    pub fn get_native_for(&self, target: &Target) -> Option<&LibraryResource> {
+        self.well_known_native_for(target)
+    }
+
+    fn well_known_native_for(&self, target: &Target) -> Option<&LibraryResource> {
+        let key = self.well_known_native_key_for(target)?;
+        self.other.as_ref().and_then(|other| other.get(key))
+    }
+
+    /// Classifier of the native for the target by its well-known name, if present.
+    fn well_known_native_key_for(&self, target: &Target) -> Option<&str> {
+        let native_str: &'static str = match target.os.as_str() {
+            "osx" if target.arch == "aarch64" => "natives-macos-arm64",
             "linux" => "natives-linux",
             "windows" => "natives-windows",
-            "macos" => "natives-macos",
-            _ => panic!("unsupported target"),
+            "osx" => "natives-macos",
+            _ => return None,
+        };
+        self.other
+            .as_ref()
+            .and_then(|other| other.get_key_value(native_str))
+            .map(|(key, _)| key.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::process::GameCommand;
+    use crate::testing::{temp_dir, version_json};
+
+    fn resource(path: &str) -> Value {
+        json!({
+            "sha1": "9bc8e3b5e8cbfa7e8cfa1a9f3ac7e69f3b6a2b81",
+            "size": 1024,
+            "url": format!("https://libraries.minecraft.net/{}", path),
+        })
+    }
+
+    /// Trimmed down 1.16.5 version json.
+    fn modern_json() -> Value {
+        json!({
+            "arguments": {
+                "game": [
+                    "--username",
+                    "${auth_player_name}",
+                    {
+                        "rules": [{ "action": "allow", "features": { "has_custom_resolution": true } }],
+                        "value": ["--width", "${resolution_width}"],
+                    },
+                ],
+                "jvm": [
+                    {
+                        "rules": [{ "action": "allow", "os": { "name": "osx" } }],
+                        "value": "-XstartOnFirstThread",
+                    },
+                    "-cp",
+                    "${classpath}",
+                ],
+            },
+            "assetIndex": {
+                "id": "1.16",
+                "sha1": "3a5d110a6ff102d5ab2ae2e3dbf2d3e1bc4bb6ab",
+                "size": 295417,
+                "totalSize": 330604389,
+                "url": "https://launchermeta.mojang.com/v1/packages/3a5d/1.16.json",
+            },
+            "assets": "1.16",
+            "complianceLevel": 1,
+            "downloads": {
+                "client": resource("client.jar"),
+                "client_mappings": resource("client.txt"),
+                "server": resource("server.jar"),
+            },
+            "id": "1.16.5",
+            "javaVersion": { "component": "jre-legacy", "majorVersion": 8 },
+            "libraries": [
+                {
+                    "downloads": { "artifact": { "path": "a/b/1.0/b-1.0.jar", "sha1": "", "size": 1, "url": "https://libraries.minecraft.net/a/b/1.0/b-1.0.jar" } },
+                    "name": "a:b:1.0",
+                },
+                {
+                    "downloads": {
+                        "classifiers": {
+                            "natives-linux": { "path": "c-natives-linux.jar", "sha1": "", "size": 2, "url": "https://libraries.minecraft.net/c-natives-linux.jar" },
+                        },
+                    },
+                    "name": "org.lwjgl:c:3.2.2",
+                    "rules": [{ "action": "allow" }, { "action": "disallow", "os": { "name": "osx" } }],
+                },
+            ],
+            "logging": {
+                "client": {
+                    "argument": "-Dlog4j.configurationFile=${path}",
+                    "file": { "id": "client-1.12.xml", "sha1": "", "size": 888, "url": "https://launcher.mojang.com/client-1.12.xml" },
+                    "type": "log4j2-xml",
+                },
+            },
+            "mainClass": "net.minecraft.client.main.Main",
+            "minimumLauncherVersion": 21,
+            "releaseTime": "2021-01-14T16:05:32Z",
+            "time": "2021-01-14T16:05:32Z",
+            "type": "release",
+        })
+    }
+
+    #[test]
+    fn version_info_round_trips() {
+        let json = modern_json();
+        let info: VersionInfo = serde_json::from_value(json.clone()).unwrap();
+        let serialized = serde_json::to_value(&info).unwrap();
+        assert_eq!(serialized, json);
+
+        let reparsed: VersionInfo = serde_json::from_value(serialized).unwrap();
+        assert_eq!(serde_json::to_value(&reparsed).unwrap(), json);
+    }
+
+    #[test]
+    fn legacy_arguments_round_trip() {
+        let mut json = modern_json();
+        let object = json.as_object_mut().unwrap();
+        object.remove("arguments");
+        object.insert(
+            "minecraftArguments".to_owned(),
+            json!("--username ${auth_player_name} --version ${version_name}"),
+        );
+        let info: VersionInfo = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&info).unwrap(), json);
+    }
+
+    #[test]
+    fn feature_gated_library_needs_its_feature() {
+        let library: Library = serde_json::from_value(json!({
+            "downloads": {},
+            "name": "a:resolution:1.0",
+            "rules": [{ "action": "allow", "features": { "has_custom_resolution": true } }],
+        }))
+        .unwrap();
+
+        assert!(!library.is_supported_by_rules());
+        assert!(!library.is_allowed(&HashMap::from([("has_custom_resolution", false)])));
+        assert!(library.is_allowed(&HashMap::from([("has_custom_resolution", true)])));
+    }
+
+    fn target(os: &str) -> Target {
+        Target {
+            os: os.to_owned(),
+            arch: "x86_64".to_owned(),
+            version: None,
+        }
+    }
+
+    #[test]
+    fn rules_are_evaluated_for_target() {
+        let rules: Rules = serde_json::from_value(json!([
+            { "action": "allow" },
+            { "action": "disallow", "os": { "name": "windows" } },
+        ]))
+        .unwrap();
+        let features = HashMap::new();
+
+        assert!(rules.is_allowed_for(&target("linux"), &features));
+        assert!(rules.is_allowed_for(&target("osx"), &features));
+        assert!(!rules.is_allowed_for(&target("windows"), &features));
+    }
+
+    #[test]
+    fn os_specific_rule_allows_only_its_os() {
+        let rules: Rules = serde_json::from_value(json!([
+            { "action": "allow", "os": { "name": "windows", "arch": "x86" } },
+        ]))
+        .unwrap();
+        let features = HashMap::new();
+        let windows_x86 = Target {
+            arch: "x86".to_owned(),
+            ..target("windows")
+        };
+
+        assert!(rules.is_allowed_for(&windows_x86, &features));
+        assert!(!rules.is_allowed_for(&target("windows"), &features));
+        assert!(!rules.is_allowed_for(&target("linux"), &features));
+    }
+
+    #[test]
+    fn classpath_ends_with_client_and_skips_disallowed_libraries() {
+        let artifact = |path: &str| {
+            let mut artifact = resource(path);
+            artifact["path"] = path.into();
+            artifact
+        };
+        let mut info =
+            crate::testing::version_json(&Url::parse("https://example.invalid/").unwrap());
+        info["libraries"] = json!([
+            { "name": "a:first:1.0", "downloads": { "artifact": artifact("a/first.jar") } },
+            {
+                "name": "a:gated:1.0",
+                "downloads": { "artifact": artifact("a/gated.jar") },
+                "rules": [{ "action": "allow", "features": { "is_demo_user": true } }],
+            },
+            { "name": "a:second:1.0", "downloads": { "artifact": artifact("a/second.jar") } },
+        ]);
+        let info: VersionInfo = serde_json::from_value(info).unwrap();
+        let hierarchy = Hierarchy::builder("/games/mc", &info.id).build();
+
+        let classpath = info.resolved_classpath(&hierarchy, &HashMap::new());
+        assert_eq!(
+            classpath,
+            [
+                hierarchy.libraries_dir.join("a/first.jar"),
+                hierarchy.libraries_dir.join("a/second.jar"),
+                hierarchy.version_dir.join("client.jar"),
+            ]
+        );
+        let classpath =
+            info.resolved_classpath(&hierarchy, &HashMap::from([("is_demo_user", true)]));
+        assert_eq!(classpath[1], hierarchy.libraries_dir.join("a/gated.jar"));
+    }
+
+    #[test]
+    fn excluded_libraries_name_their_rule() {
+        let mut info =
+            crate::testing::version_json(&Url::parse("https://example.invalid/").unwrap());
+        info["libraries"] = json!([
+            { "name": "a:everywhere:1.0", "downloads": {} },
+            {
+                "name": "a:not-on-osx:1.0",
+                "downloads": {},
+                "rules": [
+                    { "action": "allow" },
+                    { "action": "disallow", "os": { "name": "osx" } },
+                ],
+            },
+        ]);
+        let info: VersionInfo = serde_json::from_value(info).unwrap();
+        let features = HashMap::new();
+
+        assert!(info
+            .excluded_libraries(&target("linux"), &features)
+            .is_empty());
+        let excluded = info.excluded_libraries(&target("osx"), &features);
+        assert_eq!(excluded.len(), 1);
+        assert_eq!(excluded[0].name, "a:not-on-osx:1.0");
+        assert!(matches!(excluded[0].rule.action, RuleAction::Disallow));
+        assert_eq!(
+            excluded[0].rule.os.as_ref().unwrap().name.as_deref(),
+            Some("osx")
+        );
+    }
+
+    #[tokio::test]
+    async fn installed_version_launches_without_network() {
+        let dir = temp_dir();
+        let hierarchy = Hierarchy::builder(&dir, "1.12.2").build();
+        // nothing listens there, any request would fail
+        let info = version_json(&Url::parse("http://127.0.0.1:9/").unwrap());
+        std::fs::create_dir_all(&hierarchy.version_dir).unwrap();
+        std::fs::write(VersionInfo::path(&hierarchy), info.to_string()).unwrap();
+
+        let version = VersionInfo::load(&VersionInfo::path(&hierarchy))
+            .await
+            .unwrap();
+        let features = HashMap::new();
+        let command = GameCommand::from_version_info(&hierarchy, &version, &features, "Steve");
+
+        let argv = command.argv(None);
+        assert!(argv
+            .iter()
+            .any(|arg| arg == "net.minecraft.client.main.Main"));
+        assert!(argv.iter().any(|arg| arg == "Steve"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn version_1_20_requires_java_17_runtime() {
+        let mut info = version_json(&Url::parse("https://example.invalid/").unwrap());
+        info["id"] = json!("1.20");
+        info["javaVersion"] = json!({ "component": "java-runtime-gamma", "majorVersion": 17 });
+        let version: VersionInfo = serde_json::from_value(info).unwrap();
+
+        let java = version.required_java().unwrap();
+        assert_eq!(java.component, "java-runtime-gamma");
+        assert_eq!(java.major_version, 17);
+        assert_eq!(java.description(), Some("Java 17"));
+    }
+
+    #[test]
+    fn legacy_version_has_no_required_java() {
+        let info = version_json(&Url::parse("https://example.invalid/").unwrap());
+        let version: VersionInfo = serde_json::from_value(info).unwrap();
+
+        assert!(version.required_java().is_none());
+    }
+
+    #[test]
+    fn arch_placeholder_selects_native_by_bit_width() {
+        let native = |path: &str| {
+            let mut native = resource(path);
+            native["path"] = path.into();
+            native
+        };
+        let library: Library = serde_json::from_value(json!({
+            "name": "org.lwjgl.lwjgl:lwjgl-platform:2.9.4",
+            "natives": { "windows": "natives-windows-${arch}" },
+            "downloads": {
+                "classifiers": {
+                    "natives-windows-32": native("natives-windows-32.jar"),
+                    "natives-windows-64": native("natives-windows-64.jar"),
+                },
+            },
+        }))
+        .unwrap();
+        let target = |arch: &str| Target {
+            arch: arch.to_owned(),
+            ..target("windows")
+        };
+
+        let native_path = |arch| &library.get_native_for(&target(arch)).unwrap().path;
+        assert_eq!(native_path("x86"), "natives-windows-32.jar");
+        assert_eq!(native_path("x86_64"), "natives-windows-64.jar");
+    }
+
+    #[test]
+    fn single_and_array_argument_values_iterate_alike() {
+        let arguments = |value: Value| -> Arguments {
+            let info: Value = json!({
+                "arguments": {
+                    "game": ["--demo", { "rules": [{ "action": "allow" }], "value": value }],
+                    "jvm": [{ "rules": [{ "action": "allow" }], "value": value }],
+                },
+            });
+            serde_json::from_value(info).unwrap()
         };
-        self.other.as_ref().and_then(|other| other.get(native_str))
+        let single = arguments(json!("-XstartOnFirstThread"));
+        let many = arguments(json!(["-XstartOnFirstThread"]));
+        let features = HashMap::new();
+
+        for arguments in [&single, &many] {
+            assert!(arguments
+                .iter_jvm_args(&features)
+                .eq(["-XstartOnFirstThread"]));
+            assert!(arguments
+                .iter_game_args(&features)
+                .eq(["--demo", "-XstartOnFirstThread"]));
+        }
+    }
+
+    #[test]
+    fn declared_size_counts_shared_asset_objects_once() {
+        let mut info = version_json(&Url::parse("https://example.invalid/").unwrap());
+        info["downloads"]["client"]["size"] = 1000.into();
+        let mut artifact = resource("lib.jar");
+        artifact["path"] = "lib.jar".into();
+        info["libraries"] = json!([{ "name": "a:lib:1.0", "downloads": { "artifact": artifact } }]);
+        let version: VersionInfo = serde_json::from_value(info).unwrap();
+        let asset_index = |legacy: bool| -> AssetIndex {
+            serde_json::from_value(json!({
+                "map_to_resources": legacy,
+                "objects": {
+                    "sounds/a.ogg": { "hash": "aa11", "size": 30 },
+                    "sounds/b.ogg": { "hash": "aa11", "size": 30 },
+                    "lang/en.json": { "hash": "bb22", "size": 5 },
+                    "unhashed": { "size": 7 },
+                },
+            }))
+            .unwrap()
+        };
+        let target = target("linux");
+        let features = HashMap::new();
+
+        assert_eq!(
+            version.declared_size(&asset_index(false), &target, &features),
+            1000 + 1024 + 30 + 5
+        );
+        assert_eq!(
+            version.declared_size(&asset_index(true), &target, &features),
+            1000 + 1024 + 30 + 30 + 5
+        );
+    }
+
+    #[test]
+    fn chosen_native_key_prefers_declared_classifier() {
+        let native = |path: &str| {
+            let mut native = resource(path);
+            native["path"] = path.into();
+            native
+        };
+        let classifiers = json!({
+            "natives-macos": native("natives-macos.jar"),
+            "natives-macos-arm64": native("natives-macos-arm64.jar"),
+            "natives-osx": native("natives-osx.jar"),
+        });
+        let well_known: Library = serde_json::from_value(json!({
+            "name": "org.lwjgl:lwjgl:3.3.1",
+            "downloads": { "classifiers": classifiers },
+        }))
+        .unwrap();
+        let declared: Library = serde_json::from_value(json!({
+            "name": "org.lwjgl.lwjgl:lwjgl-platform:2.9.4",
+            "natives": { "osx": "natives-osx" },
+            "downloads": { "classifiers": classifiers },
+        }))
+        .unwrap();
+        let macos_arm64 = Target {
+            arch: "aarch64".to_owned(),
+            ..target("osx")
+        };
+
+        assert_eq!(
+            well_known.chosen_native_key_for(&macos_arm64),
+            Some("natives-macos-arm64")
+        );
+        assert_eq!(
+            well_known.get_native_for(&macos_arm64).unwrap().path,
+            "natives-macos-arm64.jar"
+        );
+        assert_eq!(
+            declared.chosen_native_key_for(&macos_arm64),
+            Some("natives-osx")
+        );
+        assert_eq!(
+            declared.get_native_for(&macos_arm64).unwrap().path,
+            "natives-osx.jar"
+        );
+        assert_eq!(declared.chosen_native_key_for(&target("linux")), None);
+    }
+
+    #[test]
+    fn unknown_download_keys_are_kept() {
+        let downloads: Downloads = serde_json::from_value(json!({
+            "client": resource("client.jar"),
+            "server": resource("server.jar"),
+            "windows_server": resource("windows_server.exe"),
+            "not_a_resource": 42,
+        }))
+        .unwrap();
+
+        assert_eq!(downloads.server.as_ref().unwrap().size, 1024);
+        assert_eq!(
+            downloads.get("windows_server").unwrap().url.as_str(),
+            "https://libraries.minecraft.net/windows_server.exe"
+        );
+        assert_eq!(
+            downloads.get("client").unwrap().url.as_str(),
+            "https://libraries.minecraft.net/client.jar"
+        );
+        assert!(downloads.get("not_a_resource").is_none());
+        assert_eq!(downloads.other.len(), 1);
+    }
+
+    #[test]
+    fn asset_index_id_must_match_assets() {
+        let mut info = version_json(&Url::parse("https://example.invalid/").unwrap());
+        let consistent: VersionInfo = serde_json::from_value(info.clone()).unwrap();
+        consistent.check_asset_index().unwrap();
+
+        info["assetIndex"]["id"] = "1.13".into();
+        let mismatched: VersionInfo = serde_json::from_value(info).unwrap();
+        assert!(matches!(
+            mismatched.check_asset_index(),
+            Err(crate::Error::AssetIndexMismatch { assets, asset_index })
+                if assets == "1.12" && asset_index == "1.13"
+        ));
+    }
+
+    #[test]
+    fn version_without_arguments_is_unsupported() {
+        let mut info = version_json(&Url::parse("https://example.invalid/").unwrap());
+        info.as_object_mut().unwrap().remove("minecraftArguments");
+        assert!(matches!(
+            VersionInfo::from_slice(info.to_string().as_bytes()),
+            Err(crate::Error::UnsupportedVersionFormat { id }) if id == "1.12.2"
+        ));
+
+        // other failures stay plain parse errors
+        info["minecraftArguments"] = "--username ${auth_player_name}".into();
+        info.as_object_mut().unwrap().remove("mainClass");
+        assert!(matches!(
+            VersionInfo::from_slice(info.to_string().as_bytes()),
+            Err(crate::Error::Io(e)) if e.kind() == io::ErrorKind::InvalidData
+        ));
     }
 }
@@ -1,8 +1,8 @@
 use chrono::{DateTime, Utc};
-use serde_derive::Deserialize;
+use serde_derive::{Deserialize, Serialize};
 use url::Url;
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum ReleaseType {
     Release,
@@ -11,7 +11,7 @@ pub enum ReleaseType {
     OldBeta,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Version {
     pub id: String,
@@ -20,20 +20,35 @@ pub struct Version {
     pub url: Url,
     pub time: DateTime<Utc>,
     pub release_time: DateTime<Utc>,
+    /// Only present in the v2 manifest.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha1: Option<String>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct Latest {
     pub release: String,
     pub snapshot: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct VersionsManifest {
     pub latest: Latest,
     pub versions: Vec<Version>,
 }
 
+impl ReleaseType {
+    /// Value expected by the `${version_type}` launch argument.
+    pub fn as_arg(&self) -> &'static str {
+        match self {
+            Self::Release => "release",
+            Self::Snapshot => "snapshot",
+            Self::OldAlpha => "old_alpha",
+            Self::OldBeta => "old_beta",
+        }
+    }
+}
+
 impl VersionsManifest {
     pub fn get_version(&self, id: &str) -> Option<&Version> {
         self.versions
@@ -48,4 +63,113 @@ impl VersionsManifest {
     pub fn latest_snapshot(&self) -> Option<&Version> {
         self.get_version(&self.latest.snapshot)
     }
+
+    /// Id of the latest release as declared, even if the manifest doesn't list it.
+    pub fn latest_release_id(&self) -> &str {
+        &self.latest.release
+    }
+
+    pub fn latest_snapshot_id(&self) -> &str {
+        &self.latest.snapshot
+    }
+
+    /// Releases published after the version `id`, `None` if it's not listed (e.g. a custom or
+    /// removed version).
+    pub fn releases_newer_than(&self, id: &str) -> Option<impl Iterator<Item = &Version>> {
+        let installed = self.get_version(id)?.release_time;
+        Some(self.versions.iter().filter(move |version| {
+            version.release_type == ReleaseType::Release && version.release_time > installed
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn manifest_round_trips() {
+        let json = json!({
+            "latest": { "release": "1.16.5", "snapshot": "21w03a" },
+            "versions": [
+                {
+                    "id": "21w03a",
+                    "type": "snapshot",
+                    "url": "https://launchermeta.mojang.com/v1/packages/21w03a.json",
+                    "time": "2021-01-20T14:10:24Z",
+                    "releaseTime": "2021-01-20T14:03:03Z",
+                },
+                {
+                    "id": "1.16.5",
+                    "type": "release",
+                    "url": "https://launchermeta.mojang.com/v1/packages/1.16.5.json",
+                    "time": "2021-01-14T16:05:32Z",
+                    "releaseTime": "2021-01-14T16:05:32Z",
+                },
+            ],
+        });
+        let manifest: VersionsManifest = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&manifest).unwrap(), json);
+    }
+
+    #[test]
+    fn release_type_arg_matches_json_name() {
+        for (release_type, expected) in [
+            (ReleaseType::Release, "release"),
+            (ReleaseType::Snapshot, "snapshot"),
+            (ReleaseType::OldAlpha, "old_alpha"),
+            (ReleaseType::OldBeta, "old_beta"),
+        ] {
+            assert_eq!(release_type.as_arg(), expected);
+            assert_eq!(serde_json::to_value(release_type).unwrap(), expected);
+        }
+    }
+
+    fn version(id: &str, release_type: &str, release_time: &str) -> serde_json::Value {
+        json!({
+            "id": id,
+            "type": release_type,
+            "url": format!("https://launchermeta.mojang.com/v1/packages/{}.json", id),
+            "time": release_time,
+            "releaseTime": release_time,
+        })
+    }
+
+    #[test]
+    fn newer_releases_skip_snapshots_and_older_ones() {
+        let manifest: VersionsManifest = serde_json::from_value(json!({
+            "latest": { "release": "1.17", "snapshot": "21w03a" },
+            "versions": [
+                version("1.17", "release", "2021-06-08T11:00:40Z"),
+                version("21w03a", "snapshot", "2021-01-20T14:03:03Z"),
+                version("1.16.5", "release", "2021-01-14T16:05:32Z"),
+                version("1.16.4", "release", "2020-10-29T15:49:37Z"),
+            ],
+        }))
+        .unwrap();
+
+        let newer: Vec<_> = manifest
+            .releases_newer_than("1.16.4")
+            .unwrap()
+            .map(|version| version.id.as_str())
+            .collect();
+        assert_eq!(newer, ["1.17", "1.16.5"]);
+        assert_eq!(manifest.releases_newer_than("1.17").unwrap().count(), 0);
+        assert!(manifest.releases_newer_than("custom").is_none());
+    }
+
+    #[test]
+    fn latest_ids_are_declared_ones_even_if_unlisted() {
+        let manifest: VersionsManifest = serde_json::from_value(json!({
+            "latest": { "release": "1.16.5", "snapshot": "21w03a" },
+            "versions": [version("1.16.5", "release", "2021-01-14T16:05:32Z")],
+        }))
+        .unwrap();
+
+        assert_eq!(manifest.latest_release_id(), "1.16.5");
+        assert_eq!(manifest.latest_snapshot_id(), "21w03a");
+        assert!(manifest.latest_snapshot().is_none());
+    }
 }
@@ -0,0 +1,153 @@
+use std::{fmt, io};
+
+use url::Url;
+
+use crate::resources::join_under;
+
+/// Artifact coordinate in the `group:artifact:version[:classifier][@extension]` form, as used in
+/// library names.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MavenCoordinate<'a> {
+    pub group: &'a str,
+    pub artifact: &'a str,
+    pub version: &'a str,
+    pub classifier: Option<&'a str>,
+    pub extension: &'a str,
+}
+
+impl<'a> MavenCoordinate<'a> {
+    pub fn parse(name: &'a str) -> Option<Self> {
+        let (name, extension) = match name.split_once('@') {
+            Some((name, extension)) => (name, extension),
+            None => (name, "jar"),
+        };
+        let mut parts = name.split(':');
+        let coordinate = Self {
+            group: parts.next().filter(|part| !part.is_empty())?,
+            artifact: parts.next().filter(|part| !part.is_empty())?,
+            version: parts.next().filter(|part| !part.is_empty())?,
+            classifier: parts.next(),
+            extension,
+        };
+        parts.next().is_none().then(|| coordinate)
+    }
+
+    /// Same as [`Self::parse`], but fails with an error naming the coordinate.
+    pub fn try_parse(name: &'a str) -> crate::Result<Self> {
+        Self::parse(name).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid maven coordinate: {}", name),
+            )
+            .into()
+        })
+    }
+
+    /// Path relative to the repository root (and the libraries dir), e.g.
+    /// `net/fabricmc/intermediary/1.20.4/intermediary-1.20.4.jar`.
+    pub fn path(&self) -> String {
+        format!(
+            "{}/{}/{}/{}",
+            self.group.replace('.', "/"),
+            self.artifact,
+            self.version,
+            self.file_name()
+        )
+    }
+
+    pub fn file_name(&self) -> String {
+        match self.classifier {
+            Some(classifier) => format!(
+                "{}-{}-{}.{}",
+                self.artifact, self.version, classifier, self.extension
+            ),
+            None => format!("{}-{}.{}", self.artifact, self.version, self.extension),
+        }
+    }
+
+    /// Download url of the artifact in `repository`.
+    pub fn url(&self, repository: &Url) -> crate::Result<Url> {
+        join_under(repository, &self.path())
+    }
+}
+
+impl fmt::Display for MavenCoordinate<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.group, self.artifact, self.version)?;
+        if let Some(classifier) = self.classifier {
+            write!(f, ":{}", classifier)?;
+        }
+        if self.extension != "jar" {
+            write!(f, "@{}", self.extension)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn path_of_plain_coordinate() {
+        let coordinate = MavenCoordinate::parse("net.fabricmc:intermediary:1.20.4").unwrap();
+        assert_eq!(
+            coordinate.path(),
+            "net/fabricmc/intermediary/1.20.4/intermediary-1.20.4.jar"
+        );
+        assert_eq!(coordinate.to_string(), "net.fabricmc:intermediary:1.20.4");
+    }
+
+    #[test]
+    fn path_with_classifier_and_extension() {
+        let coordinate = MavenCoordinate::parse("org.lwjgl:lwjgl:3.3.1:natives-linux@zip").unwrap();
+        assert_eq!(coordinate.classifier, Some("natives-linux"));
+        assert_eq!(
+            coordinate.path(),
+            "org/lwjgl/lwjgl/3.3.1/lwjgl-3.3.1-natives-linux.zip"
+        );
+        assert_eq!(
+            coordinate.to_string(),
+            "org.lwjgl:lwjgl:3.3.1:natives-linux@zip"
+        );
+    }
+
+    #[test]
+    fn invalid_coordinates() {
+        assert!(MavenCoordinate::parse("net.fabricmc:intermediary").is_none());
+        assert!(MavenCoordinate::parse("a:b:c:d:e").is_none());
+        assert!(MavenCoordinate::parse(":b:c").is_none());
+        assert!(MavenCoordinate::try_parse("a:b").is_err());
+    }
+
+    #[test]
+    fn url_keeps_repository_path() {
+        let coordinate = MavenCoordinate::parse("a.b:c:1").unwrap();
+        for repository in ["https://maven.example/repo", "https://maven.example/repo/"] {
+            assert_eq!(
+                coordinate
+                    .url(&Url::parse(repository).unwrap())
+                    .unwrap()
+                    .as_str(),
+                "https://maven.example/repo/a/b/c/1/c-1.jar"
+            );
+        }
+    }
+
+    #[test]
+    fn fabric_library_path_and_url() {
+        let coordinate =
+            MavenCoordinate::try_parse("net.fabricmc:tiny-mappings-parser:0.3.0+build.17").unwrap();
+        assert_eq!(
+            coordinate.path(),
+            "net/fabricmc/tiny-mappings-parser/0.3.0+build.17/tiny-mappings-parser-0.3.0+build.17.jar"
+        );
+        assert_eq!(
+            coordinate
+                .url(&Url::parse("https://maven.fabricmc.net/").unwrap())
+                .unwrap()
+                .as_str(),
+            "https://maven.fabricmc.net/net/fabricmc/tiny-mappings-parser/0.3.0+build.17/tiny-mappings-parser-0.3.0+build.17.jar"
+        );
+    }
+}
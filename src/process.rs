@@ -1,33 +1,162 @@
-use std::env::JoinPathsError;
 use std::fmt::Debug;
 use std::{
     borrow::Cow,
     collections::HashMap,
     env,
     ffi::{OsStr, OsString},
+    fs,
+    io::{self, Write},
     iter,
-    path::Path,
-    process::Command,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
 };
 
+use chrono::Utc;
+use tokio::{
+    io::{AsyncBufReadExt, BufReader, Lines},
+    process::{self, Child, ChildStderr, ChildStdout},
+    task,
+};
 use tracing::{instrument, trace};
+use zip::ZipArchive;
 
-use crate::{io::file::Hierarchy, metadata::game::VersionInfo};
+use crate::{
+    io::file::{create_temp, Hierarchy},
+    metadata::game::{Resource, VersionInfo},
+};
 
+/// Replaces every `${key}` of the argument, unknown keys are kept as is.
 #[instrument(level = "trace")]
 fn substitute_arg<'a>(arg: &'a str, params: &'a HashMap<&str, Cow<'a, OsStr>>) -> OsString {
-    if let Some(i) = arg.find("${") {
-        if let Some(j) = arg[i..].find('}') {
-            if let Some(replacement) = params.get(&arg[i + 2..i + j]) {
-                let mut output = OsString::new();
-                output.push(OsStr::new(&arg[..i]));
-                output.push(replacement);
-                output.push(OsStr::new(&arg[i + j + 1..]));
-                return output;
+    let mut output = OsString::new();
+    let mut rest = arg;
+    while let Some(i) = rest.find("${") {
+        let j = match rest[i..].find('}') {
+            Some(j) => i + j,
+            None => break,
+        };
+        output.push(OsStr::new(&rest[..i]));
+        match params.get(&rest[i + 2..j]) {
+            Some(replacement) => output.push(replacement),
+            None => output.push(OsStr::new(&rest[i..=j])),
+        }
+        rest = &rest[j + 1..];
+    }
+    output.push(OsStr::new(rest));
+    output
+}
+
+/// Separator of classpath entries, `java` doesn't accept quoting of entries containing it.
+const CLASSPATH_SEPARATOR: &str = if cfg!(windows) { ";" } else { ":" };
+
+/// G1GC tuning used by the official launcher for its default profiles.
+pub const RECOMMENDED_JVM_FLAGS: &[&str] = &[
+    "-XX:+UnlockExperimentalVMOptions",
+    "-XX:+UseG1GC",
+    "-XX:G1NewSizePercent=20",
+    "-XX:G1ReservePercent=20",
+    "-XX:MaxGCPauseMillis=50",
+    "-XX:G1HeapRegionSize=32M",
+];
+
+/// Name of a jvm option without its value, e.g. `UseG1GC` for `-XX:-UseG1GC`.
+fn jvm_option_name(arg: &str) -> &str {
+    let name = match arg.strip_prefix("-XX:") {
+        Some(option) => option.trim_start_matches(['+', '-']),
+        None => arg,
+    };
+    name.split('=').next().unwrap_or(name)
+}
+
+#[derive(Debug)]
+pub enum FileProblem {
+    Missing(PathBuf),
+    SizeMismatch {
+        path: PathBuf,
+        expected: u64,
+        actual: u64,
+    },
+    ChecksumMismatch(PathBuf),
+}
+
+async fn verify_file(path: PathBuf, resource: &Resource) -> crate::Result<Option<FileProblem>> {
+    let metadata = match tokio::fs::metadata(&path).await {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Ok(Some(FileProblem::Missing(path)))
+        }
+        Err(e) => return Err(e.into()),
+    };
+    if !resource.size_matches(metadata.len()) {
+        return Ok(Some(FileProblem::SizeMismatch {
+            path,
+            expected: resource.size,
+            actual: metadata.len(),
+        }));
+    }
+    #[cfg(feature = "sha1")]
+    if !resource.checksum().verify(path.clone()).await? {
+        return Ok(Some(FileProblem::ChecksumMismatch(path)));
+    }
+    Ok(None)
+}
+
+/// Checks the client jar and the library artifacts put on the classpath, so a broken install can
+/// be repaired before launch. Checksums are verified with the `sha1` feature only.
+#[instrument(skip(version))]
+pub async fn verify_game_files(
+    hierarchy: &Hierarchy,
+    version: &VersionInfo,
+    features: &HashMap<&str, bool>,
+) -> crate::Result<Vec<FileProblem>> {
+    let mut problems = Vec::new();
+    let client = verify_file(
+        hierarchy.version_dir.join("client.jar"),
+        &version.downloads.client,
+    );
+    problems.extend(client.await?);
+    for lib in version
+        .libraries
+        .iter()
+        .filter(|lib| lib.is_allowed(features))
+    {
+        if let Some(artifact) = &lib.resources.artifact {
+            let path = hierarchy.libraries_dir.join(&artifact.path);
+            problems.extend(verify_file(path, &artifact.resource).await?);
+        }
+    }
+    trace!(?problems, "Verified game files");
+    Ok(problems)
+}
+
+/// Checks that some classpath jar contains the main class, failing with
+/// [`crate::Error::MainClassNotFound`] otherwise. Opt-in, as every jar's index is read, but turns
+/// a misconfigured mod loader into an error before launch rather than `ClassNotFoundException`.
+#[instrument(skip(version))]
+pub async fn verify_main_class(
+    hierarchy: &Hierarchy,
+    version: &VersionInfo,
+    features: &HashMap<&str, bool>,
+) -> crate::Result<()> {
+    let classpath = version.resolved_classpath(hierarchy, features);
+    let main_class = version.main_class.clone();
+    let class_file = format!("{}.class", main_class.replace('.', "/"));
+    let found = task::spawn_blocking(move || -> crate::Result<bool> {
+        for jar in classpath.iter().filter(|jar| jar.exists()) {
+            let archive = ZipArchive::new(fs::File::open(jar)?)?;
+            if archive.file_names().any(|name| name == class_file) {
+                trace!(?jar, "Found main class");
+                return Ok(true);
             }
         }
+        Ok(false)
+    })
+    .await??;
+    if found {
+        Ok(())
+    } else {
+        Err(crate::Error::MainClassNotFound(main_class))
     }
-    OsString::from(arg)
 }
 
 #[derive(Debug)]
@@ -36,27 +165,26 @@ pub struct GameCommand<'a> {
     pub jvm_args: Vec<OsString>,
     pub game_args: Vec<OsString>,
     pub main_class: &'a str,
+    /// Major version of java the game requires, if declared by the version.
+    pub java_major_version: Option<usize>,
+    classpath: Option<OsString>,
 }
 
 impl<'a> GameCommand<'a> {
     fn build_classpath(
         version: &VersionInfo,
         hierarchy: &Hierarchy,
-    ) -> Result<OsString, JoinPathsError> {
-        env::join_paths(
-            version
-                .libraries
-                .iter()
-                .filter_map(|lib| {
-                    if lib.is_supported_by_rules() {
-                        lib.resources.artifact.as_ref()
-                    } else {
-                        None
-                    }
-                })
-                .map(|artifact| hierarchy.libraries_dir.join(&artifact.path))
-                .chain(iter::once(hierarchy.version_dir.join("client.jar"))),
-        )
+        features: &HashMap<&str, bool>,
+    ) -> crate::Result<OsString> {
+        let classpath = version.resolved_classpath(hierarchy, features);
+        // `join_paths` quotes such entries on Windows instead of failing
+        if let Some(path) = classpath
+            .iter()
+            .find(|path| path.to_string_lossy().contains(CLASSPATH_SEPARATOR))
+        {
+            return Err(crate::Error::SeparatorInClasspath(path.clone()));
+        }
+        Ok(env::join_paths(classpath)?)
     }
 
     #[instrument(level = "trace")]
@@ -65,6 +193,75 @@ impl<'a> GameCommand<'a> {
         version: &'a VersionInfo,
         features: &'b HashMap<&str, bool>,
         username: &'a str,
+    ) -> Self {
+        Self::from_version_info_with_params(hierarchy, version, features, username, &HashMap::new())
+    }
+
+    /// Same as [`Self::from_version_info`], but `overrides` replace (or add) placeholder values,
+    /// e.g. `user_properties` or `auth_access_token`.
+    #[instrument(level = "trace")]
+    pub fn from_version_info_with_params<'b: 'a>(
+        hierarchy: &'a Hierarchy,
+        version: &'a VersionInfo,
+        features: &'b HashMap<&str, bool>,
+        username: &'a str,
+        overrides: &HashMap<&'a str, Cow<'a, OsStr>>,
+    ) -> Self {
+        let classpath = match Self::build_classpath(version, hierarchy, features) {
+            Ok(classpath) => Some(classpath),
+            Err(e) => {
+                trace!(%e, "Error appending classpath to params");
+                None
+            }
+        };
+        Self::with_classpath(hierarchy, version, features, username, classpath, overrides)
+    }
+
+    /// Same as [`Self::from_version_info`], but the game runs in `game_dir` instead of
+    /// [`Hierarchy::gamedir`], so saves and options live apart from the shared assets and
+    /// libraries. It's substituted for `${game_directory}` too.
+    #[instrument(level = "trace")]
+    pub fn from_version_info_in_dir<'b: 'a>(
+        hierarchy: &'a Hierarchy,
+        version: &'a VersionInfo,
+        features: &'b HashMap<&str, bool>,
+        username: &'a str,
+        game_dir: &'a Path,
+    ) -> Self {
+        let overrides = HashMap::from([("game_directory", Cow::Borrowed(game_dir.as_os_str()))]);
+        let mut command =
+            Self::from_version_info_with_params(hierarchy, version, features, username, &overrides);
+        command.cwd = game_dir;
+        command
+    }
+
+    /// Same as [`Self::from_version_info`], but fails if the classpath can't be built, e.g. when
+    /// a library path contains the platform's path separator.
+    #[instrument(level = "trace")]
+    pub fn try_from_version_info<'b: 'a>(
+        hierarchy: &'a Hierarchy,
+        version: &'a VersionInfo,
+        features: &'b HashMap<&str, bool>,
+        username: &'a str,
+    ) -> crate::Result<Self> {
+        let classpath = Self::build_classpath(version, hierarchy, features)?;
+        Ok(Self::with_classpath(
+            hierarchy,
+            version,
+            features,
+            username,
+            Some(classpath),
+            &HashMap::new(),
+        ))
+    }
+
+    fn with_classpath<'b: 'a>(
+        hierarchy: &'a Hierarchy,
+        version: &'a VersionInfo,
+        features: &'b HashMap<&str, bool>,
+        username: &'a str,
+        classpath: Option<OsString>,
+        overrides: &HashMap<&'a str, Cow<'a, OsStr>>,
     ) -> Self {
         const LAUNCHER_NAME: &str = env!("CARGO_PKG_NAME");
         const LAUNCHER_VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -77,6 +274,16 @@ impl<'a> GameCommand<'a> {
             "natives_directory",
             Cow::Borrowed(hierarchy.natives_dir.as_os_str()),
         );
+        // used by forge to put its libraries on the module path
+        params.insert(
+            "library_directory",
+            Cow::Borrowed(hierarchy.libraries_dir.as_os_str()),
+        );
+        // used by forge in module path arguments along with the libraries dir
+        params.insert(
+            "classpath_separator",
+            Cow::Borrowed(CLASSPATH_SEPARATOR.as_ref()),
+        );
         params.insert(
             "game_directory",
             Cow::Borrowed(hierarchy.gamedir.as_os_str()),
@@ -85,32 +292,39 @@ impl<'a> GameCommand<'a> {
             "assets_root",
             Cow::Borrowed(hierarchy.assets_dir.as_os_str()),
         );
+        params.insert(
+            "game_assets",
+            Cow::Owned(hierarchy.game_assets_dir(&version.assets).into_os_string()),
+        );
 
-        match Self::build_classpath(version, hierarchy) {
-            Ok(classpath) => {
-                trace!(?classpath, "Built classpath");
-                params.insert("classpath", Cow::Owned(classpath));
-            }
-            Err(e) => {
-                trace!(%e, "Error appending classpath to params");
-            }
+        if let Some(classpath) = &classpath {
+            trace!(?classpath, "Built classpath");
+            params.insert("classpath", Cow::Owned(classpath.clone()));
         }
 
         params.insert("version_name", Cow::Borrowed(version.id.as_ref()));
         params.insert("assets_index_name", Cow::Borrowed(version.assets.as_ref()));
+        params.insert(
+            "version_type",
+            Cow::Borrowed(version.release_type.as_arg().as_ref()),
+        );
         params.insert("auth_player_name", Cow::Borrowed(username.as_ref()));
+        // legacy versions pass it as `--userProperties`, which must be a valid json
+        params.insert("user_properties", Cow::Borrowed("{}".as_ref()));
         // TODO : and so on
 
+        params.extend(overrides.iter().map(|(&key, value)| (key, value.clone())));
+
         trace!(?params, "Gather params for substitution");
 
         let jvm_args = version
             .arguments
-            .iter_jvm_args(&features)
+            .iter_jvm_args(features)
             .map(|arg| substitute_arg(arg, &params))
             .collect();
         let game_args = version
             .arguments
-            .iter_game_args(&features)
+            .iter_game_args(features)
             .map(|arg| substitute_arg(arg, &params))
             .collect();
         trace!(?jvm_args, "Compiled jvm_args");
@@ -121,16 +335,830 @@ impl<'a> GameCommand<'a> {
             main_class: &version.main_class,
             jvm_args,
             game_args,
+            java_major_version: version.java_version.as_ref().map(|java| java.major_version),
+            classpath,
         }
     }
 
+    /// Classpath substituted into the arguments, `None` if it couldn't be built.
+    pub fn classpath(&self) -> Option<&OsStr> {
+        self.classpath.as_deref()
+    }
+
+    /// Memory limits are in megabytes and passed as `-Xms`/`-Xmx`, replacing ones supplied by
+    /// the version metadata.
+    pub fn with_memory(mut self, min_mb: Option<u32>, max_mb: Option<u32>) -> Self {
+        let flags: Vec<(&str, u32)> = [("-Xms", min_mb), ("-Xmx", max_mb)]
+            .into_iter()
+            .filter_map(|(flag, mb)| mb.map(|mb| (flag, mb)))
+            .collect();
+        self.jvm_args.retain(|arg| {
+            let arg = arg.to_string_lossy();
+            !flags.iter().any(|(flag, _)| arg.starts_with(flag))
+        });
+        for (flag, mb) in flags.into_iter().rev() {
+            self.jvm_args.insert(0, format!("{}{}M", flag, mb).into());
+        }
+        self
+    }
+
+    /// Appends [`RECOMMENDED_JVM_FLAGS`], see [`Self::with_jvm_flags`].
+    pub fn with_recommended_flags(self) -> Self {
+        self.with_jvm_flags(RECOMMENDED_JVM_FLAGS)
+    }
+
+    /// Appends jvm flags, skipping ones already set (by option name, regardless of value).
+    pub fn with_jvm_flags(mut self, flags: &[&str]) -> Self {
+        for flag in flags {
+            let present = self
+                .jvm_args
+                .iter()
+                .any(|arg| jvm_option_name(&arg.to_string_lossy()) == jvm_option_name(flag));
+            if !present {
+                self.jvm_args.push(OsString::from(flag));
+            }
+        }
+        self
+    }
+
+    /// Complete ordered argument vector: java path (if given), jvm args, main class, game args.
+    pub fn argv(&self, java_path: Option<&OsStr>) -> Vec<OsString> {
+        java_path
+            .map(OsStr::to_os_string)
+            .into_iter()
+            .chain(self.jvm_args.iter().cloned())
+            .chain(iter::once(OsString::from(self.main_class)))
+            .chain(self.game_args.iter().cloned())
+            .collect()
+    }
+
     #[instrument]
     pub fn build(&self, java_path: impl AsRef<OsStr> + Debug) -> Command {
         let mut command = Command::new(java_path);
         command.current_dir(self.cwd);
-        command.args(&self.jvm_args);
-        command.arg(OsStr::new(&self.main_class));
+        command.args(self.argv(None));
+        command
+    }
+
+    /// Same as [`Self::build`], but jvm args (the classpath, mostly) are written into an argument
+    /// file passed to java as `@<path>`, so the command line stays short. Keep the returned
+    /// [`ArgFile`] alive until the game exits, it's removed on drop.
+    ///
+    /// Argument files are read by java 9 and newer only, so this fails with
+    /// [`crate::Error::ArgFileUnsupported`] for versions requiring an older java, e.g. 1.16 and
+    /// older ones requiring java 8. Nothing is checked if the version doesn't declare its java.
+    #[instrument]
+    pub fn build_with_argfile(
+        &self,
+        java_path: impl AsRef<OsStr> + Debug,
+    ) -> crate::Result<(Command, ArgFile)> {
+        if let Some(major_version) = self.java_major_version.filter(|&major| major < 9) {
+            return Err(crate::Error::ArgFileUnsupported(major_version));
+        }
+        let argfile = ArgFile::create(&self.jvm_args)?;
+        let mut argfile_arg = OsString::from("@");
+        argfile_arg.push(argfile.path());
+
+        let mut command = Command::new(java_path);
+        command.current_dir(self.cwd);
+        command.arg(argfile_arg);
+        command.arg(self.main_class);
         command.args(&self.game_args);
+        Ok((command, argfile))
+    }
+
+    /// Renders the launch command as a script for the given shell, e.g. to save it as `.sh`/`.bat`.
+    pub fn to_script(&self, shell: Shell, java_path: impl AsRef<OsStr>) -> String {
+        let mut script = String::from(shell.header());
+        script.push_str("cd ");
+        if shell == Shell::Cmd {
+            script.push_str("/d ");
+        }
+        script.push_str(&shell.quote(self.cwd.as_os_str()));
+        script.push_str(shell.line_ending());
+
+        let argv: Vec<_> = self
+            .argv(Some(java_path.as_ref()))
+            .iter()
+            .map(|arg| shell.quote(arg))
+            .collect();
+        script.push_str(&argv.join(" "));
+        script.push_str(shell.line_ending());
+        script
+    }
+
+    /// Spawns the game with piped stdout/stderr, so its output can be read line by line.
+    #[instrument]
+    pub fn spawn_with_logs(
+        &self,
+        java_path: impl AsRef<OsStr> + Debug,
+    ) -> crate::Result<GameProcess> {
+        let mut command = process::Command::from(self.build(java_path));
+        command.stdout(Stdio::piped()).stderr(Stdio::piped());
+        let mut child = command.spawn()?;
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let stderr = child.stderr.take().expect("stderr is piped");
+
+        Ok(GameProcess {
+            child,
+            stdout: BufReader::new(stdout).lines(),
+            stderr: BufReader::new(stderr).lines(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    /// POSIX `sh`.
+    Sh,
+    /// Windows `cmd.exe`.
+    Cmd,
+}
+
+impl Shell {
+    fn header(self) -> &'static str {
+        match self {
+            Self::Sh => "#!/bin/sh\n",
+            Self::Cmd => "@echo off\r\n",
+        }
+    }
+
+    fn line_ending(self) -> &'static str {
+        match self {
+            Self::Sh => "\n",
+            Self::Cmd => "\r\n",
+        }
+    }
+
+    fn quote(self, arg: &OsStr) -> String {
+        let arg = arg.to_string_lossy();
+        match self {
+            Self::Sh => format!("'{}'", arg.replace('\'', r"'\''")),
+            Self::Cmd => format!("\"{}\"", arg.replace('"', "\"\"").replace('%', "%%")),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ArgFile {
+    path: PathBuf,
+}
+
+impl ArgFile {
+    fn create(args: &[OsString]) -> io::Result<Self> {
+        let content: Vec<_> = args
+            .iter()
+            .map(|arg| {
+                let arg = arg.to_string_lossy();
+                format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+            })
+            .collect();
+        let (path, mut file) = create_temp(".args", |path| {
+            fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(path)
+        })?;
+        trace!(?path, "Writing argument file");
+        let argfile = Self { path };
+        file.write_all(content.join("\n").as_bytes())?;
+        Ok(argfile)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for ArgFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[derive(Debug)]
+pub struct GameProcess {
+    pub child: Child,
+    pub stdout: Lines<BufReader<ChildStdout>>,
+    pub stderr: Lines<BufReader<ChildStderr>>,
+}
+
+pub static EULA_URL: &str = "https://aka.ms/MinecraftEULA";
+
+#[derive(Debug)]
+pub struct ServerCommand<'a> {
+    pub cwd: &'a Path,
+    pub jar: PathBuf,
+    pub jvm_args: Vec<OsString>,
+}
+
+impl<'a> ServerCommand<'a> {
+    /// `cwd` is the server directory, where worlds, configs and `eula.txt` are kept.
+    pub fn new(hierarchy: &Hierarchy, cwd: &'a Path) -> Self {
+        Self {
+            cwd,
+            jar: hierarchy.version_dir.join("server.jar"),
+            jvm_args: Vec::new(),
+        }
+    }
+
+    /// Memory limits are in megabytes and passed as `-Xms`/`-Xmx`.
+    pub fn with_memory(mut self, min_mb: Option<u32>, max_mb: Option<u32>) -> Self {
+        if let Some(min_mb) = min_mb {
+            self.jvm_args.push(format!("-Xms{}M", min_mb).into());
+        }
+        if let Some(max_mb) = max_mb {
+            self.jvm_args.push(format!("-Xmx{}M", max_mb).into());
+        }
+        self
+    }
+
+    fn eula_path(&self) -> PathBuf {
+        self.cwd.join("eula.txt")
+    }
+
+    /// Writes `eula.txt` into the server directory. Nothing is written unless `accepted` is set,
+    /// i.e. the caller has explicitly agreed to the Minecraft EULA.
+    #[instrument]
+    pub fn accept_eula(&self, accepted: bool) -> crate::Result<()> {
+        if !accepted {
+            return Err(crate::Error::EulaNotAccepted);
+        }
+        let content = format!(
+            "#By changing the setting below to TRUE you are indicating your agreement to our EULA ({}).\n#{}\neula=true\n",
+            EULA_URL,
+            Utc::now().format("%a %b %d %H:%M:%S %Z %Y")
+        );
+        fs::create_dir_all(self.cwd)?;
+        fs::write(self.eula_path(), content)?;
+        Ok(())
+    }
+
+    pub fn is_eula_accepted(&self) -> bool {
+        fs::read_to_string(self.eula_path())
+            .map(|content| {
+                content
+                    .lines()
+                    .any(|line| line.trim().eq_ignore_ascii_case("eula=true"))
+            })
+            .unwrap_or(false)
+    }
+
+    /// Same as [`Self::build`], but refuses to produce a command until the EULA is accepted.
+    pub fn try_build(&self, java_path: impl AsRef<OsStr> + Debug) -> crate::Result<Command> {
+        if self.is_eula_accepted() {
+            Ok(self.build(java_path))
+        } else {
+            Err(crate::Error::EulaNotAccepted)
+        }
+    }
+
+    #[instrument]
+    pub fn build(&self, java_path: impl AsRef<OsStr> + Debug) -> Command {
+        let mut command = Command::new(java_path);
+        command.current_dir(self.cwd);
+        command.args(&self.jvm_args);
+        command.arg("-jar");
+        command.arg(&self.jar);
+        command.arg("nogui");
         command
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use url::Url;
+
+    use super::*;
+    use crate::metadata::game::JavaVersion;
+    use crate::testing::{temp_dir, version_json, zip};
+
+    /// Version with modern arguments, passing the classpath and the username.
+    fn modern_version() -> VersionInfo {
+        let mut info = version_json(&Url::parse("https://example.invalid/").unwrap());
+        let info_object = info.as_object_mut().unwrap();
+        info_object.remove("minecraftArguments");
+        info_object.insert(
+            "arguments".to_owned(),
+            json!({
+                "game": ["--username", "${auth_player_name}"],
+                "jvm": ["-cp", "${classpath}"],
+            }),
+        );
+        serde_json::from_value(info).unwrap()
+    }
+
+    #[test]
+    fn server_command_runs_jar_headless() {
+        let hierarchy = Hierarchy::builder("/games/mc", "1.16.5").build();
+        let cwd = Path::new("/srv/minecraft");
+        let command = ServerCommand::new(&hierarchy, cwd)
+            .with_memory(Some(1024), Some(4096))
+            .build("java");
+
+        assert_eq!(command.get_program(), "java");
+        assert_eq!(command.get_current_dir(), Some(cwd));
+        let jar = hierarchy.version_dir.join("server.jar");
+        let expected: [&OsStr; 5] = [
+            "-Xms1024M".as_ref(),
+            "-Xmx4096M".as_ref(),
+            "-jar".as_ref(),
+            jar.as_ref(),
+            "nogui".as_ref(),
+        ];
+        assert!(command.get_args().eq(expected));
+    }
+
+    #[test]
+    fn accepted_eula_is_written() {
+        let dir = temp_dir();
+        let hierarchy = Hierarchy::builder(&dir, "1.16.5").build();
+        let server_dir = dir.join("server");
+        let command = ServerCommand::new(&hierarchy, &server_dir);
+        assert!(matches!(
+            command.try_build("java"),
+            Err(crate::Error::EulaNotAccepted)
+        ));
+
+        command.accept_eula(true).unwrap();
+
+        let content = fs::read_to_string(server_dir.join("eula.txt")).unwrap();
+        let lines: Vec<_> = content.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with('#') && lines[0].contains(EULA_URL));
+        assert!(lines[1].starts_with('#'));
+        assert_eq!(lines[2], "eula=true");
+        assert!(command.try_build("java").is_ok());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn declined_eula_writes_nothing() {
+        let dir = temp_dir();
+        let hierarchy = Hierarchy::builder(&dir, "1.16.5").build();
+        let command = ServerCommand::new(&hierarchy, &dir);
+
+        assert!(matches!(
+            command.accept_eula(false),
+            Err(crate::Error::EulaNotAccepted)
+        ));
+        assert!(!dir.join("eula.txt").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn game_output_is_captured_by_lines() {
+        let dir = temp_dir();
+        let command = GameCommand {
+            cwd: &dir,
+            jvm_args: vec!["-c".into(), "echo started; echo failed >&2; pwd".into()],
+            main_class: "sh",
+            game_args: Vec::new(),
+            java_major_version: None,
+            classpath: None,
+        };
+
+        let mut process = command.spawn_with_logs("sh").unwrap();
+        let mut stdout = Vec::new();
+        while let Some(line) = process.stdout.next_line().await.unwrap() {
+            stdout.push(line);
+        }
+        let stderr = process.stderr.next_line().await.unwrap();
+
+        assert!(process.child.wait().await.unwrap().success());
+        assert_eq!(stdout, ["started", dir.to_str().unwrap()]);
+        assert_eq!(stderr.as_deref(), Some("failed"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn spaced_command() -> GameCommand<'static> {
+        GameCommand {
+            cwd: Path::new("/games/my game"),
+            jvm_args: vec![OsString::from("-Dname=it's 100%")],
+            game_args: vec![OsString::from("--username"), OsString::from("\"Steve\"")],
+            main_class: "net.minecraft.client.main.Main",
+            java_major_version: None,
+            classpath: None,
+        }
+    }
+
+    #[test]
+    fn sh_script_quotes_arguments() {
+        let script = spaced_command().to_script(Shell::Sh, "/opt/my java/bin/java");
+        assert_eq!(
+            script,
+            "#!/bin/sh\n\
+             cd '/games/my game'\n\
+             '/opt/my java/bin/java' '-Dname=it'\\''s 100%' 'net.minecraft.client.main.Main' \
+             '--username' '\"Steve\"'\n"
+        );
+    }
+
+    #[test]
+    fn cmd_script_quotes_arguments() {
+        let script = spaced_command().to_script(Shell::Cmd, r"C:\Program Files\Java\bin\java.exe");
+        assert_eq!(
+            script,
+            "@echo off\r\n\
+             cd /d \"/games/my game\"\r\n\
+             \"C:\\Program Files\\Java\\bin\\java.exe\" \"-Dname=it's 100%%\" \
+             \"net.minecraft.client.main.Main\" \"--username\" \"\"\"Steve\"\"\"\r\n"
+        );
+    }
+
+    #[test]
+    fn argv_lists_every_argument_in_order() {
+        let version = modern_version();
+        let hierarchy = Hierarchy::builder("/games/mc", &version.id).build();
+        let features = HashMap::new();
+        let command = GameCommand::from_version_info(&hierarchy, &version, &features, "Steve");
+
+        let client_jar = hierarchy.version_dir.join("client.jar");
+        let expected: [&OsStr; 6] = [
+            "java".as_ref(),
+            "-cp".as_ref(),
+            client_jar.as_ref(),
+            "net.minecraft.client.main.Main".as_ref(),
+            "--username".as_ref(),
+            "Steve".as_ref(),
+        ];
+        assert_eq!(command.argv(Some("java".as_ref())), expected);
+        assert_eq!(command.argv(None), expected[1..]);
+        assert!(command
+            .build("java")
+            .get_args()
+            .eq(expected[1..].iter().copied()));
+    }
+
+    #[test]
+    fn resolved_classpath_is_exposed() {
+        let mut version = modern_version();
+        version.libraries = serde_json::from_value(json!([{
+            "name": "com.mojang:brigadier:1.0.18",
+            "downloads": {
+                "artifact": {
+                    "path": "com/mojang/brigadier/1.0.18/brigadier-1.0.18.jar",
+                    "sha1": "",
+                    "size": 0,
+                    "url": "https://libraries.minecraft.net/com/mojang/brigadier/1.0.18/brigadier-1.0.18.jar",
+                },
+            },
+        }]))
+        .unwrap();
+        let hierarchy = Hierarchy::builder("/games/mc", &version.id).build();
+        let features = HashMap::new();
+        let command = GameCommand::from_version_info(&hierarchy, &version, &features, "Steve");
+
+        let classpath: Vec<_> = env::split_paths(command.classpath().unwrap()).collect();
+        assert_eq!(
+            classpath,
+            [
+                hierarchy
+                    .libraries_dir
+                    .join("com/mojang/brigadier/1.0.18/brigadier-1.0.18.jar"),
+                hierarchy.version_dir.join("client.jar"),
+            ]
+        );
+        assert_eq!(command.jvm_args[1], command.classpath().unwrap());
+    }
+
+    #[test]
+    fn classpath_entry_with_separator_is_rejected() {
+        let mut version = modern_version();
+        version.libraries = serde_json::from_value(json!([{
+            "name": "a:b:1.0",
+            "downloads": {
+                "artifact": {
+                    "path": "a/b/1.0/b-1.0.jar",
+                    "sha1": "",
+                    "size": 0,
+                    "url": "https://example.invalid/b-1.0.jar",
+                },
+            },
+        }]))
+        .unwrap();
+        let features = HashMap::new();
+        let odd_dir = format!("/games/odd{}dir", CLASSPATH_SEPARATOR);
+        let hierarchy = Hierarchy::builder("/games/mc", &version.id)
+            .libraries_dir(&odd_dir)
+            .build();
+
+        let result = GameCommand::try_from_version_info(&hierarchy, &version, &features, "Steve");
+        assert!(matches!(
+            result,
+            Err(crate::Error::SeparatorInClasspath(path)) if path.starts_with(&odd_dir)
+        ));
+
+        let hierarchy = Hierarchy::builder("/games/mc", &version.id).build();
+        let command =
+            GameCommand::try_from_version_info(&hierarchy, &version, &features, "Steve").unwrap();
+        let library = hierarchy.libraries_dir.join("a/b/1.0/b-1.0.jar");
+        let client_jar = hierarchy.version_dir.join("client.jar");
+        assert_eq!(
+            command.jvm_args[1],
+            env::join_paths([library, client_jar]).unwrap()
+        );
+    }
+
+    #[test]
+    fn jvm_args_are_passed_through_argfile() {
+        let dir = temp_dir();
+        let classpath: Vec<_> = (0..5000)
+            .map(|i| dir.join(format!("lib {}.jar", i)))
+            .collect();
+        let command = GameCommand {
+            cwd: &dir,
+            jvm_args: vec!["-cp".into(), env::join_paths(&classpath).unwrap()],
+            main_class: "-version",
+            game_args: Vec::new(),
+            java_major_version: Some(17),
+            classpath: None,
+        };
+
+        let (mut java, argfile) = command.build_with_argfile("java").unwrap();
+        let path = argfile.path().to_owned();
+        let mut expected = OsString::from("@");
+        expected.push(&path);
+        assert!(java
+            .get_args()
+            .eq([expected.as_os_str(), "-version".as_ref()]));
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.starts_with("\"-cp\"\n\""));
+        assert!(content.contains("lib 4999.jar"));
+        // java reads the file itself, if there's one around
+        if let Ok(output) = java.output() {
+            assert!(output.status.success());
+        }
+
+        drop(argfile);
+        assert!(!path.exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn argfile_needs_java_9() {
+        let mut command = spaced_command();
+        command.java_major_version = Some(8);
+        assert!(matches!(
+            command.build_with_argfile("java"),
+            Err(crate::Error::ArgFileUnsupported(8))
+        ));
+
+        let mut version = modern_version();
+        version.java_version = Some(JavaVersion {
+            component: "jre-legacy".to_owned(),
+            major_version: 8,
+        });
+        let hierarchy = Hierarchy::builder("/games/mc", &version.id).build();
+        let features = HashMap::new();
+        let command = GameCommand::from_version_info(&hierarchy, &version, &features, "Steve");
+        assert!(matches!(
+            command.build_with_argfile("java"),
+            Err(crate::Error::ArgFileUnsupported(8))
+        ));
+    }
+
+    #[test]
+    fn user_properties_default_to_empty_object() {
+        let mut info = version_json(&Url::parse("https://example.invalid/").unwrap());
+        info["id"] = json!("1.8.9");
+        info["minecraftArguments"] =
+            json!("--username ${auth_player_name} --userProperties ${user_properties}");
+        let version: VersionInfo = serde_json::from_value(info).unwrap();
+        let hierarchy = Hierarchy::builder("/games/mc", &version.id).build();
+        let features = HashMap::new();
+
+        let command = GameCommand::from_version_info(&hierarchy, &version, &features, "Steve");
+        assert_eq!(
+            command.game_args,
+            ["--username", "Steve", "--userProperties", "{}"]
+        );
+
+        let overrides = HashMap::from([(
+            "user_properties",
+            Cow::Borrowed(OsStr::new(r#"{"twitch_access_token":["x"]}"#)),
+        )]);
+        let command = GameCommand::from_version_info_with_params(
+            &hierarchy, &version, &features, "Steve", &overrides,
+        );
+        assert_eq!(command.game_args[3], r#"{"twitch_access_token":["x"]}"#);
+    }
+
+    #[tokio::test]
+    async fn truncated_client_jar_is_flagged() {
+        let dir = temp_dir();
+        let mut info = version_json(&Url::parse("https://example.invalid/").unwrap());
+        info["downloads"]["client"]["size"] = 6.into();
+        info["libraries"] = json!([{
+            "name": "a:b:1.0",
+            "downloads": {
+                "artifact": {
+                    "path": "a/b/1.0/b-1.0.jar",
+                    "sha1": "",
+                    "size": 3,
+                    "url": "https://example.invalid/b-1.0.jar",
+                },
+            },
+        }]);
+        let version: VersionInfo = serde_json::from_value(info).unwrap();
+        let hierarchy = Hierarchy::builder(&dir, &version.id).build();
+        let features = HashMap::new();
+        fs::create_dir_all(&hierarchy.version_dir).unwrap();
+        let client_jar = hierarchy.version_dir.join("client.jar");
+        fs::write(&client_jar, "cli").unwrap();
+
+        let problems = verify_game_files(&hierarchy, &version, &features)
+            .await
+            .unwrap();
+        assert_eq!(problems.len(), 2);
+        assert!(matches!(
+            &problems[0],
+            FileProblem::SizeMismatch { path, expected: 6, actual: 3 } if *path == client_jar
+        ));
+        assert!(matches!(
+            &problems[1],
+            FileProblem::Missing(path) if path.ends_with("b-1.0.jar")
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn memory_flags_replace_manifest_ones() {
+        let mut command = spaced_command();
+        command.jvm_args = vec![
+            "-Xmx1G".into(),
+            "-Xss1M".into(),
+            "-cp".into(),
+            "a.jar".into(),
+        ];
+
+        let command = command.with_memory(Some(512), Some(4096));
+        assert_eq!(
+            command.jvm_args,
+            ["-Xms512M", "-Xmx4096M", "-Xss1M", "-cp", "a.jar"]
+        );
+        let command = command.with_memory(None, Some(2048));
+        assert_eq!(
+            command.jvm_args,
+            ["-Xmx2048M", "-Xms512M", "-Xss1M", "-cp", "a.jar"]
+        );
+    }
+
+    #[test]
+    fn recommended_flags_skip_present_options() {
+        let mut command = spaced_command();
+        command.jvm_args = vec!["-XX:-UseG1GC".into(), "-XX:G1HeapRegionSize=16M".into()];
+
+        let command = command.with_recommended_flags();
+        assert_eq!(
+            command.jvm_args,
+            [
+                "-XX:-UseG1GC",
+                "-XX:G1HeapRegionSize=16M",
+                "-XX:+UnlockExperimentalVMOptions",
+                "-XX:G1NewSizePercent=20",
+                "-XX:G1ReservePercent=20",
+                "-XX:MaxGCPauseMillis=50",
+            ]
+        );
+        // applying them again changes nothing
+        let len = command.jvm_args.len();
+        assert_eq!(command.with_recommended_flags().jvm_args.len(), len);
+    }
+
+    #[test]
+    fn game_dir_override_sets_cwd_and_param() {
+        let mut version = modern_version();
+        version.arguments = serde_json::from_value(json!({
+            "arguments": { "game": ["--gameDir", "${game_directory}"], "jvm": [] },
+        }))
+        .unwrap();
+        let hierarchy = Hierarchy::builder("/games/mc", &version.id).build();
+        let features = HashMap::new();
+        let game_dir = Path::new("/profiles/survival");
+        let command = GameCommand::from_version_info_in_dir(
+            &hierarchy, &version, &features, "Steve", game_dir,
+        )
+        .build("java");
+
+        assert_eq!(command.get_current_dir(), Some(game_dir));
+        let args: Vec<_> = command.get_args().collect();
+        assert_eq!(args[args.len() - 2..], ["--gameDir", "/profiles/survival"]);
+    }
+
+    #[tokio::test]
+    async fn main_class_is_looked_up_in_classpath_jars() {
+        let dir = temp_dir();
+        let hierarchy = Hierarchy::builder(&dir, "1.12.2").build();
+        let info: VersionInfo = serde_json::from_value(version_json(
+            &Url::parse("https://example.invalid/").unwrap(),
+        ))
+        .unwrap();
+        let client_jar = hierarchy.version_dir.join("client.jar");
+        fs::create_dir_all(&hierarchy.version_dir).unwrap();
+
+        fs::write(
+            &client_jar,
+            zip(&[("net/minecraft/client/main/Main.class", b"")]),
+        )
+        .unwrap();
+        verify_main_class(&hierarchy, &info, &HashMap::new())
+            .await
+            .unwrap();
+
+        fs::write(
+            &client_jar,
+            zip(&[("net/minecraft/client/Other.class", b"")]),
+        )
+        .unwrap();
+        assert!(matches!(
+            verify_main_class(&hierarchy, &info, &HashMap::new()).await,
+            Err(crate::Error::MainClassNotFound(main_class))
+                if main_class == "net.minecraft.client.main.Main"
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn params() -> HashMap<&'static str, Cow<'static, OsStr>> {
+        HashMap::from([
+            ("library_directory", Cow::Borrowed(OsStr::new("/libs"))),
+            (
+                "classpath_separator",
+                Cow::Borrowed(OsStr::new(CLASSPATH_SEPARATOR)),
+            ),
+            ("auth_player_name", Cow::Borrowed(OsStr::new("Steve"))),
+        ])
+    }
+
+    #[test]
+    fn every_placeholder_is_substituted() {
+        let params = params();
+        let arg = "${library_directory}/a.jar${classpath_separator}${library_directory}/b.jar";
+        assert_eq!(
+            substitute_arg(arg, &params),
+            OsString::from(format!("/libs/a.jar{}/libs/b.jar", CLASSPATH_SEPARATOR))
+        );
+    }
+
+    #[test]
+    fn unknown_placeholders_are_kept() {
+        let params = params();
+        assert_eq!(
+            substitute_arg("${unknown}-${auth_player_name}", &params),
+            OsString::from("${unknown}-Steve")
+        );
+        assert_eq!(
+            substitute_arg("--name ${auth_player_name", &params),
+            OsString::from("--name ${auth_player_name")
+        );
+        assert_eq!(substitute_arg("plain", &params), OsString::from("plain"));
+    }
+
+    #[test]
+    fn classpath_separator_is_platform_specific() {
+        let expected = if cfg!(windows) { ";" } else { ":" };
+        assert_eq!(CLASSPATH_SEPARATOR, expected);
+
+        let version: VersionInfo = serde_json::from_value(serde_json::json!({
+            "id": "1.20.4",
+            "type": "release",
+            "minimumLauncherVersion": 21,
+            "releaseTime": "2023-12-07T12:56:20+00:00",
+            "time": "2023-12-07T12:56:20+00:00",
+            "libraries": [],
+            "downloads": {
+                "client": { "sha1": "", "size": 0, "url": "https://example.invalid/client.jar" },
+            },
+            "assetIndex": {
+                "id": "12",
+                "sha1": "",
+                "size": 0,
+                "url": "https://example.invalid/12.json",
+                "totalSize": 0,
+            },
+            "assets": "12",
+            "mainClass": "net.minecraft.client.main.Main",
+            "arguments": {
+                "game": [],
+                "jvm": ["-p", "a.jar${classpath_separator}b.jar"],
+            },
+        }))
+        .unwrap();
+        let hierarchy = Hierarchy::builder("/games/minecraft", &version.id).build();
+        let features = HashMap::new();
+        let command = GameCommand::from_version_info(&hierarchy, &version, &features, "Steve");
+        assert_eq!(
+            command.jvm_args,
+            [
+                OsString::from("-p"),
+                OsString::from(format!("a.jar{}b.jar", expected))
+            ]
+        );
+        assert_eq!(
+            command.classpath(),
+            Some(hierarchy.version_dir.join("client.jar").as_os_str())
+        );
+    }
+}
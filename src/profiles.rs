@@ -0,0 +1,235 @@
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+    process::ExitStatus,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::{fs, process::Child};
+use tracing::{instrument, trace};
+
+use crate::io::file::Hierarchy;
+
+pub static PROFILES_FILENAME: &str = "launcher_profiles.json";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Profile {
+    pub name: String,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub profile_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_used: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_version_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub game_dir: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub java_dir: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub java_args: Option<String>,
+    /// Not used by the vanilla launcher, accumulated by [`track_session`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_play_time_millis: Option<u64>,
+    /// Fields unknown to this crate, kept so other launchers' data survives a save.
+    #[serde(flatten)]
+    pub other: HashMap<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct LauncherProfiles {
+    pub profiles: HashMap<String, Profile>,
+    #[serde(flatten)]
+    pub other: HashMap<String, Value>,
+}
+
+impl Profile {
+    pub fn record_launch(&mut self) {
+        self.last_used = Some(Utc::now());
+    }
+
+    pub fn record_play_time(&mut self, elapsed: Duration) {
+        let elapsed = u64::try_from(elapsed.as_millis()).unwrap_or(u64::MAX);
+        let total = self.total_play_time_millis.unwrap_or(0);
+        self.total_play_time_millis = Some(total.saturating_add(elapsed));
+    }
+
+    pub fn total_play_time(&self) -> Duration {
+        Duration::from_millis(self.total_play_time_millis.unwrap_or(0))
+    }
+}
+
+impl LauncherProfiles {
+    pub fn path(hierarchy: &Hierarchy) -> PathBuf {
+        hierarchy.gamedir.join(PROFILES_FILENAME)
+    }
+
+    /// Reads profiles from `path`, an empty file is created if it doesn't exist yet.
+    #[instrument]
+    pub async fn load(path: &Path) -> crate::Result<Self> {
+        if !path.exists() {
+            trace!("Profiles not exist, creating new");
+            let profiles = Self::default();
+            profiles.save(path).await?;
+            return Ok(profiles);
+        }
+        let filebuf = fs::read(path).await?;
+        Ok(serde_json::from_slice(&filebuf)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?)
+    }
+
+    #[instrument(skip(self))]
+    pub async fn save(&self, path: &Path) -> crate::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        let filebuf = serde_json::to_vec_pretty(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(path, filebuf).await?;
+        Ok(())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Profile> {
+        self.profiles.get(key)
+    }
+
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut Profile> {
+        self.profiles.get_mut(key)
+    }
+
+    pub fn insert(&mut self, key: impl Into<String>, profile: Profile) -> Option<Profile> {
+        self.profiles.insert(key.into(), profile)
+    }
+}
+
+/// Waits for the spawned game to exit, recording the launch time and the session duration into
+/// profile `key` of the profiles file at `path`. Profiles are re-read before each write, so
+/// changes made while the game was running aren't lost.
+#[instrument(skip(child))]
+pub async fn track_session(child: &mut Child, path: &Path, key: &str) -> crate::Result<ExitStatus> {
+    let started = Instant::now();
+    let mut profiles = LauncherProfiles::load(path).await?;
+    if let Some(profile) = profiles.get_mut(key) {
+        profile.record_launch();
+        profiles.save(path).await?;
+    }
+
+    let status = child.wait().await?;
+    let elapsed = started.elapsed();
+    trace!(?elapsed, ?status, "Game exited");
+
+    let mut profiles = LauncherProfiles::load(path).await?;
+    if let Some(profile) = profiles.get_mut(key) {
+        profile.record_play_time(elapsed);
+        profiles.save(path).await?;
+    }
+    Ok(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::testing::temp_dir;
+
+    #[tokio::test]
+    async fn profiles_round_trip() {
+        let dir = temp_dir();
+        let path = dir.join(PROFILES_FILENAME);
+        let original = json!({
+            "profiles": {
+                "fe6c4e3c9e8a2b7d": {
+                    "name": "Modded",
+                    "type": "custom",
+                    "created": "2021-06-01T10:00:00Z",
+                    "lastUsed": "2021-06-02T12:30:00Z",
+                    "icon": "Furnace",
+                    "lastVersionId": "1.16.5",
+                    "gameDir": "/games/modded",
+                    "javaDir": "/usr/bin/java",
+                    "javaArgs": "-Xmx2G",
+                    "resolution": {"width": 854, "height": 480},
+                },
+            },
+            "settings": {"crashAssistance": true},
+            "version": 3,
+        });
+        std::fs::write(&path, original.to_string()).unwrap();
+
+        let mut profiles = LauncherProfiles::load(&path).await.unwrap();
+        let profile = profiles.get("fe6c4e3c9e8a2b7d").unwrap();
+        assert_eq!(profile.last_version_id.as_deref(), Some("1.16.5"));
+        assert_eq!(profile.java_args.as_deref(), Some("-Xmx2G"));
+        assert_eq!(
+            profile.other["resolution"],
+            json!({"width": 854, "height": 480})
+        );
+        profiles.save(&path).await.unwrap();
+        let saved: Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(saved, original);
+
+        profiles.insert(
+            "new",
+            Profile {
+                name: "Vanilla".to_owned(),
+                last_version_id: Some("1.18.2".to_owned()),
+                ..Default::default()
+            },
+        );
+        profiles.save(&path).await.unwrap();
+        let reloaded = LauncherProfiles::load(&path).await.unwrap();
+        assert_eq!(reloaded.get("new").unwrap().name, "Vanilla");
+        assert_eq!(reloaded.profiles.len(), 2);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn missing_file_is_created() {
+        let dir = temp_dir();
+        let path = dir.join("minecraft").join(PROFILES_FILENAME);
+
+        let profiles = LauncherProfiles::load(&path).await.unwrap();
+        assert!(profiles.profiles.is_empty());
+        assert!(path.exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn session_is_recorded() {
+        let dir = temp_dir();
+        let path = dir.join(PROFILES_FILENAME);
+        let mut profiles = LauncherProfiles::default();
+        profiles.insert(
+            "vanilla",
+            Profile {
+                name: "Vanilla".to_owned(),
+                ..Default::default()
+            },
+        );
+        profiles.save(&path).await.unwrap();
+
+        let before = Utc::now();
+        let mut child = tokio::process::Command::new("sleep")
+            .arg("0.1")
+            .spawn()
+            .unwrap();
+        let status = track_session(&mut child, &path, "vanilla").await.unwrap();
+        assert!(status.success());
+
+        let profiles = LauncherProfiles::load(&path).await.unwrap();
+        let profile = profiles.get("vanilla").unwrap();
+        assert!(profile.last_used.unwrap() >= before);
+        assert!(profile.total_play_time() >= Duration::from_millis(100));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
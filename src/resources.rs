@@ -1,27 +1,332 @@
+use std::io;
+
 use reqwest::Client;
+use tracing::instrument;
 use url::Url;
 
-use crate::metadata::{assets::AssetMetadata, manifest::VersionsManifest};
+use crate::metadata::{assets::AssetMetadata, game::VersionInfo, manifest::VersionsManifest};
 
 pub static VERSIONS_MANIFEST_URL: &str =
-    "https://launchermeta.mojang.com/mc/game/version_manifest.json";
-pub static RESOURCE_REGISTRY_URL: &str = "http://resources.download.minecraft.net";
+    "https://launchermeta.mojang.com/mc/game/version_manifest_v2.json";
+pub static RESOURCE_REGISTRY_URL: &str = "https://resources.download.minecraft.net";
+
+/// Mojang hosts with the base url each of them is served from, all canonical by default. Point
+/// any of them to a mirror, urls listed in metadata are rewritten with [`Hosts::rewrite`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hosts {
+    /// `launchermeta.mojang.com`, the versions manifest.
+    pub launcher_meta: Url,
+    /// `piston-meta.mojang.com`, version jsons and asset indices.
+    pub piston_meta: Url,
+    /// `piston-data.mojang.com`, game jars, mappings and logging configs.
+    pub piston_data: Url,
+    /// `launcher.mojang.com`, game jars of older versions.
+    pub launcher: Url,
+    /// `libraries.minecraft.net`.
+    pub libraries: Url,
+    /// `resources.download.minecraft.net`, asset objects.
+    pub resources: Url,
+}
+
+impl Default for Hosts {
+    fn default() -> Self {
+        let canonical = |host: &str| {
+            Url::parse(&format!("https://{}/", host)).expect("canonical host is a valid url")
+        };
+        Self {
+            launcher_meta: canonical("launchermeta.mojang.com"),
+            piston_meta: canonical("piston-meta.mojang.com"),
+            piston_data: canonical("piston-data.mojang.com"),
+            launcher: canonical("launcher.mojang.com"),
+            libraries: canonical("libraries.minecraft.net"),
+            resources: canonical("resources.download.minecraft.net"),
+        }
+    }
+}
+
+impl Hosts {
+    /// Moves `url` to the configured base if it's on one of the canonical hosts, e.g.
+    /// `https://libraries.minecraft.net/a/b.jar` becomes `https://mirror/maven/a/b.jar` for the
+    /// `https://mirror/maven/` base. Other urls are returned as is.
+    pub fn rewrite(&self, url: &Url) -> crate::Result<Url> {
+        let canonical = Self::default();
+        let pairs = [
+            (&canonical.launcher_meta, &self.launcher_meta),
+            (&canonical.piston_meta, &self.piston_meta),
+            (&canonical.piston_data, &self.piston_data),
+            (&canonical.launcher, &self.launcher),
+            (&canonical.libraries, &self.libraries),
+            (&canonical.resources, &self.resources),
+        ];
+        let base = match pairs
+            .into_iter()
+            .find(|(canonical, _)| url.host_str() == canonical.host_str())
+        {
+            Some((canonical, base)) if canonical != base => base,
+            _ => return Ok(url.clone()),
+        };
+        let mut rewritten = join_under(base, url.path().trim_start_matches('/'))?;
+        rewritten.set_query(url.query());
+        Ok(rewritten)
+    }
+
+    pub fn manifest_url(&self) -> crate::Result<Url> {
+        self.rewrite(&Url::parse(VERSIONS_MANIFEST_URL)?)
+    }
+}
+
+/// Joins the relative `path` onto `base`, keeping the last segment of the base even if it has no
+/// trailing slash, e.g. a maven repository url.
+pub(crate) fn join_under(base: &Url, path: &str) -> crate::Result<Url> {
+    let mut base = base.clone();
+    if !base.path().ends_with('/') {
+        base.set_path(&format!("{}/", base.path()));
+    }
+    Ok(base.join(path)?)
+}
 
 pub async fn fetch_manifest(client: &Client) -> crate::Result<VersionsManifest> {
+    fetch_manifest_with_hosts(client, &Hosts::default()).await
+}
+
+pub async fn fetch_manifest_with_hosts(
+    client: &Client,
+    hosts: &Hosts,
+) -> crate::Result<VersionsManifest> {
     Ok(client
-        .get(VERSIONS_MANIFEST_URL)
+        .get(hosts.manifest_url()?)
         .send()
         .await?
         .json()
         .await?)
 }
 
-pub fn get_asset_url(asset_metadata: &AssetMetadata) -> Url {
-    Url::parse(&format!(
+pub async fn fetch_version_info(client: &Client, url: Url) -> crate::Result<VersionInfo> {
+    fetch_version_info_with_hosts(client, url, &Hosts::default()).await
+}
+
+pub async fn fetch_version_info_with_hosts(
+    client: &Client,
+    url: Url,
+    hosts: &Hosts,
+) -> crate::Result<VersionInfo> {
+    let filebuf = client
+        .get(hosts.rewrite(&url)?)
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+    VersionInfo::from_slice(&filebuf)
+}
+
+/// Looks the version up in the manifest and fetches its json, nothing is saved to disk.
+#[instrument(skip(client))]
+pub async fn fetch_version_info_by_id(client: &Client, id: &str) -> crate::Result<VersionInfo> {
+    fetch_version_info_by_id_with_hosts(client, id, &Hosts::default()).await
+}
+
+#[instrument(skip(client))]
+pub async fn fetch_version_info_by_id_with_hosts(
+    client: &Client,
+    id: &str,
+    hosts: &Hosts,
+) -> crate::Result<VersionInfo> {
+    let manifest = fetch_manifest_with_hosts(client, hosts).await?;
+    let version = manifest
+        .get_version(id)
+        .ok_or_else(|| crate::Error::UnknownVersion(id.to_owned()))?;
+    fetch_version_info_with_hosts(client, version.url.clone(), hosts).await
+}
+
+/// Same as [`fetch_version_info_by_id`], for a given manifest, e.g. of a mirror.
+#[instrument(skip(client, manifest))]
+pub async fn fetch_listed_version_info(
+    client: &Client,
+    manifest: &VersionsManifest,
+    id: &str,
+) -> crate::Result<VersionInfo> {
+    let version = manifest
+        .get_version(id)
+        .ok_or_else(|| crate::Error::UnknownVersion(id.to_owned()))?;
+    fetch_version_info(client, version.url.clone()).await
+}
+
+pub fn get_asset_url_with_hosts(
+    asset_metadata: &AssetMetadata,
+    hosts: &Hosts,
+) -> crate::Result<Url> {
+    hosts.rewrite(&get_asset_url(asset_metadata)?)
+}
+
+/// Objects are served under the first 2 chars of their hash, so shorter hashes are rejected.
+pub fn get_asset_url(asset_metadata: &AssetMetadata) -> crate::Result<Url> {
+    let hash = &asset_metadata.hash;
+    let prefix = hash
+        .get(..2)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "asset hash is too short"))?;
+    Ok(Url::parse(&format!(
         "{}/{}/{}",
-        RESOURCE_REGISTRY_URL,
-        &asset_metadata.hash[..2],
-        &asset_metadata.hash
-    ))
-    .unwrap()
+        RESOURCE_REGISTRY_URL, prefix, hash
+    ))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::testing::{version_json, MockServer};
+
+    fn metadata(hash: &str) -> AssetMetadata {
+        AssetMetadata {
+            hash: hash.to_owned(),
+            size: 0,
+        }
+    }
+
+    #[test]
+    fn asset_url_is_split_by_hash_prefix() {
+        let url = get_asset_url(&metadata("bdf48ef6b5d0d23bbb02e17d04865216179f510a")).unwrap();
+        assert_eq!(
+            url.as_str(),
+            "https://resources.download.minecraft.net/bd/bdf48ef6b5d0d23bbb02e17d04865216179f510a"
+        );
+    }
+
+    #[test]
+    fn short_hash_is_rejected() {
+        for hash in ["", "b"] {
+            assert!(matches!(
+                get_asset_url(&metadata(hash)),
+                Err(crate::Error::Io(e)) if e.kind() == io::ErrorKind::InvalidData
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn listed_version_is_fetched_by_id() {
+        let server = MockServer::start().await;
+        server.set("1.12.2.json", version_json(&server.url("")).to_string());
+        let manifest: VersionsManifest = serde_json::from_value(json!({
+            "latest": { "release": "1.12.2", "snapshot": "1.12.2" },
+            "versions": [{
+                "id": "1.12.2",
+                "type": "release",
+                "url": server.url("1.12.2.json"),
+                "time": "2017-09-18T08:39:46+00:00",
+                "releaseTime": "2017-09-18T08:39:46+00:00",
+            }],
+        }))
+        .unwrap();
+        let client = Client::new();
+
+        let info = fetch_listed_version_info(&client, &manifest, "1.12.2")
+            .await
+            .unwrap();
+        assert_eq!(info.id, "1.12.2");
+        assert!(matches!(
+            fetch_listed_version_info(&client, &manifest, "1.16.5").await,
+            Err(crate::Error::UnknownVersion(id)) if id == "1.16.5"
+        ));
+        assert_eq!(server.hits("1.12.2.json"), 1);
+    }
+
+    fn rewrite(hosts: &Hosts, url: &str) -> String {
+        hosts.rewrite(&Url::parse(url).unwrap()).unwrap().into()
+    }
+
+    #[test]
+    fn default_hosts_keep_urls() {
+        let hosts = Hosts::default();
+        let url = "https://libraries.minecraft.net/a/b/c.jar";
+        assert_eq!(rewrite(&hosts, url), url);
+        assert_eq!(
+            hosts.manifest_url().unwrap().as_str(),
+            VERSIONS_MANIFEST_URL
+        );
+    }
+
+    #[test]
+    fn overridden_hosts_are_rewritten() {
+        let hosts = Hosts {
+            libraries: Url::parse("https://mirror.example/maven").unwrap(),
+            resources: Url::parse("https://mirror.example/assets/").unwrap(),
+            launcher_meta: Url::parse("http://meta.example").unwrap(),
+            ..Default::default()
+        };
+        assert_eq!(
+            rewrite(&hosts, "https://libraries.minecraft.net/a/b/c.jar"),
+            "https://mirror.example/maven/a/b/c.jar"
+        );
+        assert_eq!(
+            rewrite(
+                &hosts,
+                "https://resources.download.minecraft.net/ab/abcd?x=1"
+            ),
+            "https://mirror.example/assets/ab/abcd?x=1"
+        );
+        assert_eq!(
+            hosts.manifest_url().unwrap().as_str(),
+            "http://meta.example/mc/game/version_manifest_v2.json"
+        );
+        // hosts not overridden and foreign ones stay the same
+        for url in [
+            "https://piston-data.mojang.com/v1/objects/abc/client.jar",
+            "https://maven.fabricmc.net/a.jar",
+        ] {
+            assert_eq!(rewrite(&hosts, url), url);
+        }
+    }
+
+    #[test]
+    fn asset_url_follows_resources_host() {
+        let metadata = AssetMetadata {
+            hash: "abcdef".to_owned(),
+            size: 1,
+        };
+        let hosts = Hosts {
+            resources: Url::parse("https://mirror.example/assets").unwrap(),
+            ..Default::default()
+        };
+        assert_eq!(
+            get_asset_url_with_hosts(&metadata, &hosts)
+                .unwrap()
+                .as_str(),
+            "https://mirror.example/assets/ab/abcdef"
+        );
+    }
+
+    #[tokio::test]
+    async fn version_is_fetched_through_mirror() {
+        let server = MockServer::start().await;
+        server.set(
+            "meta/mc/game/version_manifest_v2.json",
+            json!({
+                "latest": { "release": "1.12.2", "snapshot": "1.12.2" },
+                "versions": [{
+                    "id": "1.12.2",
+                    "type": "release",
+                    "url": "https://piston-meta.mojang.com/v1/packages/abc/1.12.2.json",
+                    "time": "2017-09-18T08:39:46+00:00",
+                    "releaseTime": "2017-09-18T08:39:46+00:00",
+                }],
+            })
+            .to_string(),
+        );
+        server.set(
+            "piston/v1/packages/abc/1.12.2.json",
+            version_json(&server.url("")).to_string(),
+        );
+        let hosts = Hosts {
+            launcher_meta: server.url("meta/"),
+            piston_meta: server.url("piston/"),
+            ..Default::default()
+        };
+
+        let info = fetch_version_info_by_id_with_hosts(&Client::new(), "1.12.2", &hosts)
+            .await
+            .unwrap();
+        assert_eq!(info.id, "1.12.2");
+    }
 }
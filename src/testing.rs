@@ -0,0 +1,312 @@
+//! Fixtures shared by the unit tests.
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::{Cursor, Write},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, Once,
+    },
+};
+
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use tracing::{
+    field::{Field, Visit},
+    span, Event, Level, Metadata, Subscriber,
+};
+use url::Url;
+use zip::{write::FileOptions, ZipWriter};
+
+/// Creates an empty directory, unique per call, under the system temp dir.
+pub fn temp_dir() -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let dir = std::env::temp_dir().join(format!(
+        "mcl-rs-test-{}-{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    if dir.exists() {
+        fs::remove_dir_all(&dir).unwrap();
+    }
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+pub fn zip(entries: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    for (name, content) in entries {
+        zip.start_file(*name, FileOptions::default()).unwrap();
+        zip.write_all(content).unwrap();
+    }
+    zip.finish().unwrap().into_inner()
+}
+
+/// Spans recorded by [`Collector`], others are disabled to keep the tests fast.
+const RECORDED_SPANS: &[&str] = &["extract_natives", "sha1_digest"];
+
+/// Fields rendered as `field=value` pairs.
+struct Fields(Vec<String>);
+
+impl Visit for Fields {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0.push(format!("{}={:?}", field.name(), value));
+    }
+}
+
+/// Global subscriber keeping warnings, errors and the [`RECORDED_SPANS`], with their fields.
+#[derive(Default)]
+struct Collector {
+    warnings: Mutex<Vec<String>>,
+    spans: Mutex<Vec<(&'static str, Vec<String>)>>,
+}
+
+impl Collector {
+    /// Runs `f` on the collector, installed on first use. It's global so that events of
+    /// blocking tasks are caught too.
+    fn with<R>(mut f: impl FnMut(&Self) -> R) -> R {
+        static INSTALL: Once = Once::new();
+
+        INSTALL.call_once(|| tracing::subscriber::set_global_default(Self::default()).unwrap());
+        tracing::dispatcher::get_default(|dispatch| f(dispatch.downcast_ref().unwrap()))
+    }
+}
+
+impl Subscriber for Collector {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        if metadata.is_span() {
+            RECORDED_SPANS.contains(&metadata.name())
+        } else {
+            *metadata.level() <= Level::WARN
+        }
+    }
+
+    fn new_span(&self, attributes: &span::Attributes<'_>) -> span::Id {
+        let mut fields = Fields(Vec::new());
+        attributes.record(&mut fields);
+        let mut spans = self.spans.lock().unwrap();
+        spans.push((attributes.metadata().name(), fields.0));
+        span::Id::from_u64(spans.len() as u64)
+    }
+
+    fn record(&self, id: &span::Id, values: &span::Record<'_>) {
+        let mut fields = Fields(Vec::new());
+        values.record(&mut fields);
+        let index = id.into_u64() as usize - 1;
+        self.spans.lock().unwrap()[index].1.extend(fields.0);
+    }
+
+    fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = Fields(Vec::new());
+        event.record(&mut fields);
+        self.warnings.lock().unwrap().push(fields.0.join(" "));
+    }
+
+    fn enter(&self, _: &span::Id) {}
+
+    fn exit(&self, _: &span::Id) {}
+}
+
+/// Warnings logged so far by any test: filter them by something unique to the test, e.g. its
+/// temp dir. Call it once before the code under test, so the collector is installed.
+pub fn warnings() -> Vec<String> {
+    Collector::with(|collector| collector.warnings.lock().unwrap().clone())
+}
+
+/// Fields of the spans named `name` recorded so far by any test, see [`warnings`].
+pub fn spans(name: &str) -> Vec<String> {
+    Collector::with(|collector| {
+        let spans = collector.spans.lock().unwrap();
+        spans
+            .iter()
+            .filter(|(span, _)| *span == name)
+            .map(|(_, fields)| fields.join(" "))
+            .collect()
+    })
+}
+
+/// Keep-alive HTTP/1.1 server answering `GET` and `HEAD` requests of its files, anything
+/// else is 404.
+#[derive(Clone)]
+pub struct MockServer {
+    url: Url,
+    files: Arc<Mutex<HashMap<String, Vec<u8>>>>,
+    hits: Arc<Mutex<HashMap<String, usize>>>,
+    connections: Arc<AtomicUsize>,
+}
+
+impl MockServer {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = Self {
+            url: Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap(),
+            files: Default::default(),
+            hits: Default::default(),
+            connections: Default::default(),
+        };
+        let handle = server.clone();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                handle.connections.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(handle.clone().serve(socket));
+            }
+        });
+        server
+    }
+
+    /// Server running on a runtime of its own thread, for tests without one.
+    #[cfg(feature = "blocking")]
+    pub fn start_in_background() -> Self {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+            runtime.block_on(async {
+                sender.send(Self::start().await).unwrap();
+                std::future::pending::<()>().await
+            });
+        });
+        receiver.recv().unwrap()
+    }
+
+    pub fn url(&self, path: &str) -> Url {
+        self.url.join(path).unwrap()
+    }
+
+    pub fn set(&self, path: &str, content: impl Into<Vec<u8>>) {
+        self.files
+            .lock()
+            .unwrap()
+            .insert(path.to_owned(), content.into());
+    }
+
+    /// How many times `path` was requested, found or not.
+    pub fn hits(&self, path: &str) -> usize {
+        self.hits.lock().unwrap().get(path).copied().unwrap_or(0)
+    }
+
+    /// How many connections were accepted so far.
+    pub fn connections(&self) -> usize {
+        self.connections.load(Ordering::SeqCst)
+    }
+
+    async fn serve(self, mut socket: TcpStream) {
+        let mut buf = [0; 4096];
+        let mut request = Vec::new();
+        loop {
+            let len = match socket.read(&mut buf).await {
+                Ok(0) | Err(_) => return,
+                Ok(len) => len,
+            };
+            request.extend_from_slice(&buf[..len]);
+            while let Some(end) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                let head = String::from_utf8_lossy(&request[..end]).into_owned();
+                request.drain(..end + 4);
+                if socket.write_all(&self.respond(&head)).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+
+    fn respond(&self, head: &str) -> Vec<u8> {
+        let mut request_line = head.lines().next().unwrap_or_default().split(' ');
+        let method = request_line.next().unwrap_or_default();
+        let target = request_line.next().unwrap_or_default();
+        // proxied requests carry the absolute url
+        let path = match Url::parse(target) {
+            Ok(url) => url.path().to_owned(),
+            Err(_) => target.to_owned(),
+        };
+        let path = path.trim_start_matches('/');
+        *self
+            .hits
+            .lock()
+            .unwrap()
+            .entry(path.to_owned())
+            .or_default() += 1;
+
+        let file = self.files.lock().unwrap().get(path).cloned();
+        let (status, body) = match file {
+            Some(body) => ("200 OK", body),
+            None => ("404 Not Found", Vec::new()),
+        };
+        let mut response = format!(
+            "HTTP/1.1 {}\r\nContent-Length: {}\r\n\r\n",
+            status,
+            body.len()
+        )
+        .into_bytes();
+        if method != "HEAD" {
+            response.extend(body);
+        }
+        response
+    }
+}
+
+/// Minimal legacy (pre-1.13) version json, without libraries, its files are hosted at `base`.
+pub fn version_json(base: &Url) -> Value {
+    json!({
+        "id": "1.12.2",
+        "type": "release",
+        "minimumLauncherVersion": 18,
+        "releaseTime": "2017-09-18T08:39:46+00:00",
+        "time": "2017-09-18T08:39:46+00:00",
+        "libraries": [],
+        "downloads": {
+            "client": {
+                "sha1": "",
+                "size": 0,
+                "url": base.join("client.jar").unwrap(),
+            },
+        },
+        "assetIndex": {
+            "id": "1.12",
+            "sha1": "",
+            "size": 0,
+            "url": base.join("1.12.json").unwrap(),
+            "totalSize": 0,
+        },
+        "assets": "1.12",
+        "mainClass": "net.minecraft.client.main.Main",
+        "minecraftArguments": "--username ${auth_player_name} --version ${version_name}",
+    })
+}
+
+/// Library having natives for linux, windows and osx, hosted at `base`.
+pub fn natives_library_json(base: &Url, name: &str) -> Value {
+    let classifier = |os: &str| {
+        let path = format!("{}-natives-{}.jar", name, os);
+        json!({
+            "url": base.join(&path).unwrap(),
+            "path": path,
+            "sha1": "",
+            "size": 0,
+        })
+    };
+    json!({
+        "name": format!("org.lwjgl:{}:3.2.2", name),
+        "natives": {
+            "linux": "natives-linux",
+            "windows": "natives-windows",
+            "osx": "natives-macos",
+        },
+        "downloads": {
+            "classifiers": {
+                "natives-linux": classifier("linux"),
+                "natives-windows": classifier("windows"),
+                "natives-macos": classifier("macos"),
+            },
+        },
+    })
+}